@@ -0,0 +1,80 @@
+//! Per block cost of writing and snapshotting, persistent map checkpoints against
+//! cloning a std `HashMap`.
+//!
+//! ```text
+//! cargo bench --bench snapshots
+//! ```
+//!
+//! Each run fills a store with `keys` keys, then goes through `BLOCKS` blocks
+//! writing `WRITES_PER_BLOCK` keys and taking a snapshot after every block, the
+//! way block-by-block assertions do. The writes and snapshots are timed, not the
+//! seeding, so the copies persistent map writes make after a checkpoint count too.
+use std::{collections::HashMap, hint::black_box, time::{Duration, Instant}};
+use substreams::{prelude::*, scalar::BigInt};
+use Stores_and_Deltas::mock_store::{checkpoint::StoreCheckpoint, delta::DeltaRecorder, store::MockStore};
+
+const BLOCKS: u64 = 100;
+const WRITES_PER_BLOCK: u64 = 10;
+
+/// what the stores used to keep their values in, key -> (ordinal, value) versions
+type StdMap = HashMap<String, Vec<(u64, Vec<u8>)>>;
+
+fn main() {
+    println!("{:>10} {:>18} {:>18} {:>10}", "keys", "HashMap + clone", "store + checkpoint", "speedup");
+    for keys in [1_000u64, 10_000, 100_000] {
+        let clone = bench_std_clone(keys);
+        let checkpoint = bench_checkpoint(keys);
+        println!(
+            "{:>10} {:>15.2?}/blk {:>15.2?}/blk {:>9.0}x",
+            keys,
+            clone / BLOCKS as u32,
+            checkpoint / BLOCKS as u32,
+            clone.as_secs_f64() / checkpoint.as_secs_f64().max(1e-9),
+        );
+    }
+}
+
+fn key(i: u64) -> String {
+    format!("pool:{:08x}:volume", i)
+}
+
+fn bench_std_clone(keys: u64) -> Duration {
+    let mut map = StdMap::new();
+    for i in 0..keys {
+        map.entry(key(i)).or_default().push((i, BigInt::from(i).to_string().into_bytes()));
+    }
+
+    let mut snapshots = Vec::with_capacity(BLOCKS as usize);
+    let start = Instant::now();
+    for block in 0..BLOCKS {
+        for i in 0..WRITES_PER_BLOCK {
+            let k = (block * WRITES_PER_BLOCK + i) % keys;
+            map.entry(key(k)).or_default().push((i, BigInt::from(block).to_string().into_bytes()));
+        }
+        snapshots.push(map.clone());
+    }
+    let elapsed = start.elapsed();
+    black_box(snapshots);
+    elapsed
+}
+
+fn bench_checkpoint(keys: u64) -> Duration {
+    let store = <MockStore as StoreNew>::new();
+    for i in 0..keys {
+        store.set(i, key(i), &BigInt::from(i));
+    }
+
+    let mut snapshots = Vec::with_capacity(BLOCKS as usize);
+    let start = Instant::now();
+    for block in 0..BLOCKS {
+        store.begin_block(block + 1);
+        for i in 0..WRITES_PER_BLOCK {
+            let k = (block * WRITES_PER_BLOCK + i) % keys;
+            store.set(i, key(k), &BigInt::from(block));
+        }
+        snapshots.push(store.checkpoint());
+    }
+    let elapsed = start.elapsed();
+    black_box(snapshots);
+    elapsed
+}
@@ -1 +1,3 @@
+#![allow(non_snake_case)] // the crate is named after the repo, Stores-and-Deltas
+
 pub mod mock_store;
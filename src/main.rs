@@ -1,3 +1,243 @@
-fn main() {
-    println!("Hello, world!");
+#![allow(non_snake_case)]
+
+//! Inspects store snapshots captured from test runs, see `mock_store::snapshot`.
+//!
+//! ```text
+//! Stores-and-Deltas keys volume.json --prefix pair:
+//! Stores-and-Deltas get volume.json volume --at 12
+//! Stores-and-Deltas --value-type bigint dump volume.json --format json
+//! Stores-and-Deltas --descriptors uniswap-v3.spkg dump pools.json
+//! Stores-and-Deltas diff before.json after.json --out changes.pb
+//! Stores-and-Deltas apply before.json changes.pb -o after.json
+//! ```
+use std::{fs, path::{Path, PathBuf}, process::ExitCode};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Map, Value};
+use substreams::pb::substreams::{store_delta::Operation, StoreDeltas};
+use Stores_and_Deltas::mock_store::{
+    format::FormatValue,
+    handle::StoreHandle,
+    jsonl,
+    snapshot::Snapshot,
+    store::{HasHandle, MockStore, StoreConfig, StoreScan},
+    text_format::{self, DescriptorPool},
+    value_type::ValueType,
+};
+
+#[derive(Parser)]
+#[command(about = "Inspect mock store snapshots")]
+struct Cli {
+    /// how to decode values, defaults to the value type saved in the snapshot
+    #[arg(long, global = true)]
+    value_type: Option<ValueType>,
+
+    /// `.spkg` or `FileDescriptorSet` file, proto values are then shown in text format
+    #[arg(long, global = true)]
+    descriptors: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the keys, sorted
+    Keys {
+        /// snapshot file written by `Snapshot::save`
+        snapshot: PathBuf,
+        #[arg(long, default_value = "")]
+        prefix: String,
+    },
+    /// Print the last value of a key, or the one written at an ordinal
+    Get {
+        snapshot: PathBuf,
+        key: String,
+        #[arg(long)]
+        at: Option<u64>,
+    },
+    /// Print every version of a key
+    History { snapshot: PathBuf, key: String },
+    /// Key, version and size counts
+    Stats { snapshot: PathBuf },
+    /// Print every key with its versions
+    Dump {
+        snapshot: PathBuf,
+        #[arg(long, value_enum, default_value_t = DumpFormat::Text)]
+        format: DumpFormat,
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// only the last version of each key
+        #[arg(long)]
+        last_only: bool,
+    },
+    /// Keys added, removed and changed between two snapshots
+    Diff {
+        before: PathBuf,
+        after: PathBuf,
+        /// also write the changes as a protobuf `StoreDeltas`
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Replay a deltas file onto a snapshot, checking old values
+    Apply {
+        snapshot: PathBuf,
+        /// protobuf `StoreDeltas`, or JSON Lines when it ends in .jsonl
+        deltas: PathBuf,
+        #[arg(short, long)]
+        out: PathBuf,
+        /// block the deltas belong to, defaults to the snapshot's, JSON lines with a `block` keep theirs
+        #[arg(long)]
+        block: Option<u64>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DumpFormat {
+    Text,
+    Json,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let pool = match &cli.descriptors {
+        Some(path) => Some(DescriptorPool::from_file(path).map_err(|e| format!("{}: {}", path.display(), e))?),
+        None => None,
+    };
+    let show = |store: &MockStore, bytes: &[u8]| text_format::format_value(store, pool.as_ref(), bytes);
+
+    match cli.command {
+        Command::Keys { snapshot, prefix } => {
+            let (_, store) = open(&snapshot, &cli.value_type)?;
+            for key in store.keys_with_prefix(&prefix) {
+                println!("{}", key);
+            }
+        }
+        Command::Get { snapshot, key, at } => {
+            let (_, store) = open(&snapshot, &cli.value_type)?;
+            let bytes = match at {
+                Some(ord) => store.handle().get_bytes_at(ord, &key),
+                None => store.handle().get_bytes_last(&key),
+            };
+            match (bytes, at) {
+                (Some(bytes), _) => println!("{}", show(&store, &bytes)),
+                (None, Some(ord)) => return Err(format!("no value for key {:?} at ordinal {}", key, ord)),
+                (None, None) => return Err(format!("key {:?} not found", key)),
+            }
+        }
+        Command::History { snapshot, key } => {
+            let (snapshot, store) = open(&snapshot, &cli.value_type)?;
+            let versions = snapshot.entries.get(&key).ok_or_else(|| format!("key {:?} not found", key))?;
+            for version in versions {
+                println!("#{} {}", version.ordinal, show(&store, &version.value));
+            }
+        }
+        Command::Stats { snapshot } => {
+            let (snapshot, store) = open(&snapshot, &cli.value_type)?;
+            let versions: usize = snapshot.entries.values().map(|v| v.len()).sum();
+            let value_bytes: usize = snapshot.entries.values().flatten().map(|v| v.value.len()).sum();
+            let live_bytes: usize = snapshot.entries.keys().filter_map(|k| snapshot.get_last(k)).map(|v| v.len()).sum();
+
+            println!("value type  {}", store.value_label());
+            println!("block       {}", snapshot.block);
+            println!("keys        {}", snapshot.entries.len());
+            println!("versions    {}", versions);
+            println!("bytes       {} ({} in last versions)", value_bytes, live_bytes);
+        }
+        Command::Dump { snapshot, format: DumpFormat::Text, prefix, last_only } => {
+            let (_, store) = open(&snapshot, &cli.value_type)?;
+            let dump = store.dump().prefix(&prefix);
+            let dump = if last_only { dump.last_only() } else { dump };
+            let dump = match &pool {
+                Some(pool) => dump.descriptors(pool),
+                None => dump,
+            };
+            println!("{}", dump);
+        }
+        Command::Dump { snapshot, format: DumpFormat::Json, prefix, last_only } => {
+            let (snapshot, store) = open(&snapshot, &cli.value_type)?;
+            let value_type = store.value_type();
+            let mut keys = Map::new();
+            for (key, versions) in snapshot.entries.range(prefix.clone()..).take_while(|(k, _)| k.starts_with(&prefix)) {
+                let versions = if last_only { &versions[versions.len().saturating_sub(1)..] } else { &versions[..] };
+                let versions = versions
+                    .iter()
+                    .map(|v| json!({ "ordinal": v.ordinal, "value": jsonl::decode_value(value_type.as_ref(), &v.value) }))
+                    .collect();
+                keys.insert(key.clone(), Value::Array(versions));
+            }
+            println!("{}", serde_json::to_string_pretty(&Value::Object(keys)).expect("json values always serialize"));
+        }
+        Command::Diff { before, after, out } => {
+            let (before, _) = open(&before, &cli.value_type)?;
+            // decode with the newer snapshot's value type, it's the one being checked
+            let (after, store) = open(&after, &cli.value_type)?;
+            if store.value_type().is_none() {
+                store.configure(None, before.value_type.clone());
+            }
+
+            let deltas = before.diff(&after);
+            for delta in &deltas {
+                match Operation::try_from(delta.operation).unwrap_or(Operation::Unset) {
+                    Operation::Create => println!("+ {:?} {}", delta.key, show(&store, &delta.new_value)),
+                    Operation::Delete => println!("- {:?} {}", delta.key, show(&store, &delta.old_value)),
+                    _ => println!(
+                        "~ {:?} {} -> {}",
+                        delta.key,
+                        show(&store, &delta.old_value),
+                        show(&store, &delta.new_value)
+                    ),
+                }
+            }
+
+            if let Some(out) = out {
+                let bytes = substreams::proto::encode(&StoreDeltas { deltas })
+                    .expect("error when encoding store deltas");
+                fs::write(&out, bytes).map_err(|e| format!("{}: {}", out.display(), e))?;
+            }
+        }
+        Command::Apply { snapshot: path, deltas, out, block } => {
+            let (mut snapshot, store) = open(&path, &cli.value_type)?;
+            let value_type = store.value_type();
+            let block = block.unwrap_or(snapshot.block);
+
+            let read_err = |e: &dyn std::fmt::Display| format!("{}: {}", deltas.display(), e);
+            let deltas = if deltas.extension().is_some_and(|ext| ext == "jsonl") {
+                let content = fs::read_to_string(&deltas).map_err(|e| read_err(&e))?;
+                jsonl::read_block_deltas(&content, value_type.as_ref(), block).map_err(|e| read_err(&e))?
+            } else {
+                let bytes = fs::read(&deltas).map_err(|e| read_err(&e))?;
+                let deltas = substreams::proto::decode::<StoreDeltas>(&bytes).map_err(|e| read_err(&e))?.deltas;
+                // all one block, apply puts it in ordinal order
+                vec![(block, deltas)]
+            };
+
+            snapshot.apply(&deltas).map_err(|e| e.to_string())?;
+            snapshot.save(&out).map_err(|e| format!("{}: {}", out.display(), e))?;
+            let count: usize = deltas.iter().map(|(_, deltas)| deltas.len()).sum();
+            println!("applied {} deltas up to block {}, wrote {}", count, snapshot.block, out.display());
+        }
+    }
+
+    Ok(())
+}
+
+// a plain MockStore decodes through its configured value type, the flag wins over the file
+fn open(path: &Path, value_type: &Option<ValueType>) -> Result<(Snapshot, MockStore), String> {
+    let snapshot = Snapshot::from_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let store: MockStore = snapshot.restore();
+    if value_type.is_some() {
+        store.configure(None, value_type.clone());
+    }
+    Ok((snapshot, store))
 }
@@ -0,0 +1,370 @@
+//! Assertion macros for store state and recorded deltas.
+//!
+//! Comparing raw `Vec<u8>` or unwrapping `get_last` by hand gives useless failure
+//! messages, these decode the values through the same `FromBytes`/`FromBytesProto`
+//! paths the stores use and list every mismatch when they fail.
+//!
+//! ```no_run
+//! # use substreams::prelude::*;
+//! # use Stores_and_Deltas::{assert_deltas, assert_store, assert_store_absent, mock_store::{delta::DeltaRecorder, store::MockStore}};
+//! # let store = <MockStore as StoreNew>::new();
+//! assert_store!(store, "volume" => BigInt::from(15), "pair:0xab" => 3i64);
+//! assert_store_absent!(store, "pair:0xcd");
+//!
+//! assert_deltas!(store.store_deltas(12), [
+//!     Create "volume" => BigInt::from(10),
+//!     Update "volume" BigInt::from(10) -> BigInt::from(15),
+//!     Delete "pair:0xcd",
+//! ]);
+//! ```
+//!
+//! The expected values pick the decoding, so they need a concrete type: `5i64`, not `5`.
+use std::{fmt::Debug, marker::PhantomData};
+use substreams::{
+    pb::substreams::{store_delta::Operation, StoreDelta},
+    prelude::StoreGet,
+};
+use crate::mock_store::traits::{FromBytes, FromBytesProto};
+
+/// `get_last` with the value type taken from `_like`, used by `assert_store!`.
+pub fn get_last_like<T, S: StoreGet<T>>(store: &S, key: &str, _like: &T) -> Option<T> {
+    store.get_last(key)
+}
+
+/// Picks how to decode delta bytes from the type of an expected value.
+///
+/// Both decoding traits are implemented for every prost message (and `i64`, `String`..
+/// are prost messages), so the macros use autoref specialization: `FromBytes` wins
+/// when the type has it, `FromBytesProto` is the fallback.
+pub struct Decoder<T>(PhantomData<T>);
+
+impl<T> Decoder<T> {
+    pub fn of(_like: &T) -> Self {
+        Decoder(PhantomData)
+    }
+}
+
+pub trait DecodeStoreBytes {
+    type Value;
+
+    fn decode_value(&self, bytes: &[u8]) -> Self::Value;
+}
+
+impl<T: FromBytes> DecodeStoreBytes for Decoder<T> {
+    type Value = T;
+
+    fn decode_value(&self, bytes: &[u8]) -> T {
+        T::from_bytes(bytes)
+    }
+}
+
+pub trait DecodeProtoBytes {
+    type Value;
+
+    fn decode_value(&self, bytes: &[u8]) -> Self::Value;
+}
+
+impl<T: FromBytesProto> DecodeProtoBytes for &Decoder<T> {
+    type Value = T;
+
+    fn decode_value(&self, bytes: &[u8]) -> T {
+        <T as FromBytesProto>::from_bytes(bytes)
+    }
+}
+
+/// Walks the actual deltas alongside the expected ones, used by `assert_deltas!`.
+pub struct DeltaChecker<'a> {
+    deltas: &'a [StoreDelta],
+    next: usize,
+    lines: Vec<String>,
+    failed: bool,
+}
+
+impl<'a> DeltaChecker<'a> {
+    pub fn new(deltas: &'a [StoreDelta]) -> Self {
+        Self { deltas, next: 0, lines: Vec::new(), failed: false }
+    }
+
+    pub fn create<T: PartialEq + Debug>(&mut self, key: &str, new_value: T, decode: impl Fn(&[u8]) -> T) {
+        let expected = format!("Create {:?} => {:?}", key, new_value);
+        self.check(expected, |actual| {
+            if actual.operation != Operation::Create as i32 || actual.key != key {
+                return None;
+            }
+            let actual_new = decode(&actual.new_value);
+            Some((actual_new == new_value, format!("Create {:?} => {:?}", actual.key, actual_new)))
+        });
+    }
+
+    pub fn update<T: PartialEq + Debug>(&mut self, key: &str, old_value: T, new_value: T, decode: impl Fn(&[u8]) -> T) {
+        let expected = format!("Update {:?} {:?} -> {:?}", key, old_value, new_value);
+        self.check(expected, |actual| {
+            if actual.operation != Operation::Update as i32 || actual.key != key {
+                return None;
+            }
+            let actual_old = decode(&actual.old_value);
+            let actual_new = decode(&actual.new_value);
+            Some((
+                actual_old == old_value && actual_new == new_value,
+                format!("Update {:?} {:?} -> {:?}", actual.key, actual_old, actual_new),
+            ))
+        });
+    }
+
+    pub fn delete(&mut self, key: &str) {
+        let expected = format!("Delete {:?}", key);
+        self.check(expected, |actual| {
+            if actual.operation != Operation::Delete as i32 || actual.key != key {
+                return None;
+            }
+            Some((true, format!("Delete {:?}", actual.key)))
+        });
+    }
+
+    /// Panics listing every expected delta next to the actual one if anything differed.
+    #[track_caller]
+    pub fn finish(mut self) {
+        for (idx, actual) in self.deltas.iter().enumerate().skip(self.next) {
+            self.failed = true;
+            self.lines.push(format!("  #{} unexpected {}", idx, raw_delta(actual)));
+        }
+
+        if self.failed {
+            panic!("deltas assertion failed:\n{}", self.lines.join("\n"));
+        }
+    }
+
+    // `compare` returns None when the operation or key don't match, the values
+    // are only decoded once we know they are the ones we expect
+    fn check(&mut self, expected: String, compare: impl FnOnce(&StoreDelta) -> Option<(bool, String)>) {
+        let idx = self.next;
+        self.next += 1;
+
+        let Some(actual) = self.deltas.get(idx) else {
+            self.failed = true;
+            self.lines.push(format!("  #{} missing    {}", idx, expected));
+            return;
+        };
+
+        match compare(actual) {
+            Some((true, _)) => self.lines.push(format!("  #{} ok         {}", idx, expected)),
+            Some((false, got)) => {
+                self.failed = true;
+                self.lines.push(format!("  #{} mismatch   expected {}\n                got      {}", idx, expected, got));
+            }
+            None => {
+                self.failed = true;
+                self.lines.push(format!(
+                    "  #{} mismatch   expected {}\n                got      {}",
+                    idx,
+                    expected,
+                    raw_delta(actual)
+                ));
+            }
+        }
+    }
+}
+
+// used when we don't know the value type, strings are the common case so show them as such
+fn raw_delta(delta: &StoreDelta) -> String {
+    let operation = Operation::try_from(delta.operation).unwrap_or(Operation::Unset);
+    format!(
+        "{:?} {:?} {:?} -> {:?}",
+        operation,
+        delta.key,
+        String::from_utf8_lossy(&delta.old_value),
+        String::from_utf8_lossy(&delta.new_value)
+    )
+}
+
+/// Asserts the last value of each key, decoded as the type of the expected value.
+#[macro_export]
+macro_rules! assert_store {
+    ($store:expr, $($key:expr => $value:expr),+ $(,)?) => {{
+        let store = &$store;
+        let mut failures: Vec<String> = Vec::new();
+        $(
+            let key: &str = ::std::convert::AsRef::<str>::as_ref(&$key);
+            let expected = $value;
+            match $crate::mock_store::assertions::get_last_like(store, key, &expected) {
+                Some(actual) if actual == expected => {}
+                Some(actual) => failures.push(format!("  {:?}: expected {:?}, got {:?}", key, expected, actual)),
+                None => failures.push(format!("  {:?}: expected {:?}, got nothing", key, expected)),
+            }
+        )+
+        if !failures.is_empty() {
+            panic!("store assertion failed:\n{}", failures.join("\n"));
+        }
+    }};
+}
+
+/// Asserts none of the keys are in the store.
+#[macro_export]
+macro_rules! assert_store_absent {
+    ($store:expr, $($key:expr),+ $(,)?) => {{
+        use $crate::mock_store::store::StoreScan as _;
+        let store = &$store;
+        let mut present: Vec<String> = Vec::new();
+        $(
+            let key: &str = ::std::convert::AsRef::<str>::as_ref(&$key);
+            if store.keys_with_prefix(key).iter().any(|k| k == key) {
+                present.push(format!("  {:?}", key));
+            }
+        )+
+        if !present.is_empty() {
+            panic!("store absent assertion failed, these keys are present:\n{}", present.join("\n"));
+        }
+    }};
+}
+
+/// Asserts a list of `StoreDelta`s, in order:
+/// `[Create "k" => v, Update "k" old -> new, Delete "k"]`.
+#[macro_export]
+macro_rules! assert_deltas {
+    ($deltas:expr, [$($entries:tt)*]) => {{
+        #[allow(unused_imports)]
+        use $crate::mock_store::assertions::{DecodeProtoBytes as _, DecodeStoreBytes as _};
+        let deltas = &$deltas;
+        let deltas: &[::substreams::pb::substreams::StoreDelta] = ::std::convert::AsRef::as_ref(deltas);
+        #[allow(unused_mut)]
+        let mut checker = $crate::mock_store::assertions::DeltaChecker::new(deltas);
+        $crate::assert_deltas!(@entry checker; $($entries)*);
+        checker.finish();
+    }};
+
+    (@entry $checker:ident;) => {};
+    (@entry $checker:ident; Delete $key:tt $(, $($rest:tt)*)?) => {
+        $checker.delete(::std::convert::AsRef::<str>::as_ref(&$key));
+        $( $crate::assert_deltas!(@entry $checker; $($rest)*); )?
+    };
+    (@entry $checker:ident; Create $key:tt => $($rest:tt)*) => {
+        $crate::assert_deltas!(@create $checker; $key; []; $($rest)*);
+    };
+    (@entry $checker:ident; Update $key:tt $($rest:tt)*) => {
+        $crate::assert_deltas!(@update_old $checker; $key; []; $($rest)*);
+    };
+
+    // values are any tokens up to the next top level `,` (or `->` for the old value)
+    (@create $checker:ident; $key:tt; [$($value:tt)*]; $(, $($rest:tt)*)?) => {
+        {
+            let expected = $($value)*;
+            let decoder = $crate::mock_store::assertions::Decoder::of(&expected);
+            $checker.create(::std::convert::AsRef::<str>::as_ref(&$key), expected, |bytes: &[u8]| (&decoder).decode_value(bytes));
+        }
+        $( $crate::assert_deltas!(@entry $checker; $($rest)*); )?
+    };
+    (@create $checker:ident; $key:tt; [$($value:tt)*]; $next:tt $($rest:tt)*) => {
+        $crate::assert_deltas!(@create $checker; $key; [$($value)* $next]; $($rest)*);
+    };
+
+    (@update_old $checker:ident; $key:tt; [$($old:tt)*]; -> $($rest:tt)*) => {
+        $crate::assert_deltas!(@update_new $checker; $key; [$($old)*]; []; $($rest)*);
+    };
+    (@update_old $checker:ident; $key:tt; [$($old:tt)*]; $next:tt $($rest:tt)*) => {
+        $crate::assert_deltas!(@update_old $checker; $key; [$($old)* $next]; $($rest)*);
+    };
+
+    (@update_new $checker:ident; $key:tt; [$($old:tt)*]; [$($new:tt)*]; $(, $($rest:tt)*)?) => {
+        {
+            let old_value = $($old)*;
+            let new_value = $($new)*;
+            let decoder = $crate::mock_store::assertions::Decoder::of(&new_value);
+            $checker.update(::std::convert::AsRef::<str>::as_ref(&$key), old_value, new_value, |bytes: &[u8]| (&decoder).decode_value(bytes));
+        }
+        $( $crate::assert_deltas!(@entry $checker; $($rest)*); )?
+    };
+    (@update_new $checker:ident; $key:tt; [$($old:tt)*]; [$($new:tt)*]; $next:tt $($rest:tt)*) => {
+        $crate::assert_deltas!(@update_new $checker; $key; [$($old)*]; [$($new)* $next]; $($rest)*);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use substreams::{pb::substreams::StoreDelta, prelude::*};
+    use crate::mock_store::{delta::DeltaRecorder, store::{MockProtoStore, MockStore}};
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    struct Pool {
+        #[prost(uint64, tag = "1")]
+        fee: u64,
+    }
+
+    fn panic_message(f: impl FnOnce()) -> String {
+        let err = catch_unwind(AssertUnwindSafe(f)).expect_err("assertion should have failed");
+        err.downcast_ref::<String>().cloned().unwrap_or_default()
+    }
+
+    fn store() -> MockStore {
+        let store = <MockStore as StoreNew>::new();
+        store.begin_block(12);
+        store.add(1, "volume", BigInt::from(10));
+        store.add(2, "volume", BigInt::from(5));
+        store.set(3, "pair:0xab", &3i64);
+        store.delete_prefix(4, &"pair:".to_string());
+        store
+    }
+
+    #[test]
+    fn store_assertions_pass_and_list_every_mismatch() {
+        let store = store();
+        crate::assert_store!(store, "volume" => BigInt::from(15));
+        crate::assert_store_absent!(store, "pair:0xab", "pair:0xcd");
+
+        let message = panic_message(|| crate::assert_store!(store, "volume" => BigInt::from(1), "count" => 2i64));
+        assert_eq!(
+            message,
+            "store assertion failed:\n  \"volume\": expected BigInt(1), got BigInt(15)\n  \"count\": expected 2, got nothing"
+        );
+    }
+
+    #[test]
+    fn delta_entries_take_any_expression() {
+        let deltas = store().store_deltas(12);
+        crate::assert_deltas!(deltas, [
+            Create "volume" => BigInt::from(5 + 5),
+            Update "volume" BigInt::from(10) -> BigInt::from(10) + BigInt::from(5),
+            Create "pair:0xab" => 3i64,
+            Delete "pair:0xab",
+        ]);
+    }
+
+    #[test]
+    fn proto_deltas_are_decoded_as_messages() {
+        let store = <MockProtoStore<Pool> as StoreNew>::new();
+        store.set(1, "pool", &Pool { fee: 3 });
+        crate::assert_deltas!(store.store_deltas(0), [Create "pool" => Pool { fee: 3 }]);
+    }
+
+    #[test]
+    fn delta_failures_show_expected_next_to_actual() {
+        let deltas = store().store_deltas(12);
+        let message = panic_message(|| {
+            crate::assert_deltas!(deltas, [
+                Create "volume" => BigInt::from(10),
+                Update "volume" BigInt::from(10) -> BigInt::from(16),
+                Delete "pair:0xab",
+            ])
+        });
+
+        assert_eq!(
+            message,
+            [
+                "deltas assertion failed:",
+                "  #0 ok         Create \"volume\" => BigInt(10)",
+                "  #1 mismatch   expected Update \"volume\" BigInt(10) -> BigInt(16)",
+                "                got      Update \"volume\" BigInt(10) -> BigInt(15)",
+                "  #2 mismatch   expected Delete \"pair:0xab\"",
+                "                got      Create \"pair:0xab\" \"\" -> \"3\"",
+                "  #3 unexpected Delete \"pair:0xab\" \"3\" -> \"\"",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn missing_deltas_are_reported() {
+        let message = panic_message(|| crate::assert_deltas!(Vec::<StoreDelta>::new(), [Delete "volume"]));
+        assert_eq!(message, "deltas assertion failed:\n  #0 missing    Delete \"volume\"");
+    }
+}
@@ -0,0 +1,60 @@
+//! Constant time copies of the mock stores.
+//!
+//! The values live in a persistent map, so a checkpoint shares them with the
+//! store instead of copying them and only the parts later writes touch get
+//! copied. Taking one per block for block-by-block assertions no longer makes
+//! tests quadratic in the store size.
+//!
+//! ```no_run
+//! # use substreams::prelude::*;
+//! # use Stores_and_Deltas::{assert_store, mock_store::{checkpoint::StoreCheckpoint, store::MockStore}};
+//! # let store = MockStore::default();
+//! # let blocks: Vec<u64> = Vec::new();
+//! # let handler = |_block: &u64, _store: &MockStore| {};
+//! let mut checkpoints = Vec::new();
+//! for block in blocks {
+//!     handler(&block, &store);
+//!     checkpoints.push(store.checkpoint());
+//! }
+//! assert_store!(checkpoints[3], "pool:0xab:volume" => BigInt::from(10));
+//! ```
+use crate::mock_store::{handle::StoreHandle, store::HasHandle};
+
+pub trait StoreCheckpoint: Sized {
+    /// A detached copy of the store as it is now, with its values, block, deleted
+    /// prefixes and configuration but none of its recorded deltas. Writes to
+    /// either side don't show up in the other.
+    fn checkpoint(&self) -> Self;
+}
+
+impl<S: HasHandle> StoreCheckpoint for S {
+    fn checkpoint(&self) -> Self {
+        let copy = S::from_handle(Default::default());
+        *copy.handle().write() = self.handle().read().without_deltas();
+        copy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{delta::DeltaRecorder, store::MockStore};
+
+    #[test]
+    fn checkpoints_are_detached_from_the_store() {
+        let store = <MockStore as StoreNew>::new();
+        store.begin_block(1);
+        store.set(1, "volume", &10i64);
+        store.set(2, "count", &1i64);
+
+        let checkpoint = store.checkpoint();
+        store.set(3, "volume", &20i64);
+        checkpoint.set(3, "count", &2i64);
+
+        assert_eq!(<MockStore as StoreGet<i64>>::get_last(&checkpoint, "volume"), Some(10));
+        assert_eq!(<MockStore as StoreGet<i64>>::get_last(&store, "count"), Some(1));
+        // the checkpoint only records what was written to it
+        assert_eq!(checkpoint.store_deltas(1).len(), 1);
+    }
+}
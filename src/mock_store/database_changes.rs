@@ -0,0 +1,241 @@
+//! Turns recorded store deltas into database-changes rows.
+//!
+//! SQL sinks consume a `db_out` module emitting `DatabaseChanges`: one
+//! `TableChange` per row (table, primary key, operation, old/new value of each
+//! field). `db_out` usually just walks store deltas, so with a mapping from keys
+//! to tables the expected rows can be built straight from a mock store and
+//! compared with what the module emits.
+//!
+//! ```no_run
+//! # use substreams::prelude::*;
+//! # use Stores_and_Deltas::mock_store::{
+//! #     database_changes::{proto_field, value_field, DbMapping, TableMapping},
+//! #     delta::DeltaRecorder, key, store::MockStore, value_type::ValueType,
+//! # };
+//! # #[derive(Clone, PartialEq, ::prost::Message)]
+//! # struct Pair { #[prost(uint64, tag = "1")] fee: u64 }
+//! # let store = <MockStore as StoreNew>::new();
+//! let mapping = DbMapping::new()
+//!     .table(TableMapping::new("pool:", "pools").field("volume", value_field(ValueType::BigInt)))
+//!     .table(
+//!         TableMapping::new("pair:", "pairs")
+//!             .primary_key(|key| key::segment_at_owned(key.to_string(), 1))
+//!             .field("fee", proto_field(|pair: &Pair| pair.fee.to_string())),
+//!     );
+//!
+//! let changes = mapping.changes(&store.store_deltas(12));
+//! ```
+//!
+//! The messages mirror `sf.substreams.sink.database.v1` (same field tags, single
+//! column primary keys only) so `db_out` output decodes into them.
+use substreams::pb::substreams::{store_delta::Operation as DeltaOperation, StoreDelta};
+use crate::mock_store::{traits::FromBytesProto, value_type::ValueType};
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DatabaseChanges {
+    #[prost(message, repeated, tag = "1")]
+    pub table_changes: Vec<TableChange>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TableChange {
+    #[prost(string, tag = "1")]
+    pub table: String,
+    #[prost(string, tag = "2")]
+    pub pk: String,
+    #[prost(uint64, tag = "3")]
+    pub ordinal: u64,
+    #[prost(enumeration = "Operation", tag = "4")]
+    pub operation: i32,
+    #[prost(message, repeated, tag = "5")]
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Field {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub new_value: String,
+    #[prost(string, tag = "3")]
+    pub old_value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Operation {
+    Unset = 0,
+    Create = 1,
+    Update = 2,
+    Delete = 3,
+}
+
+/// Decodes one column out of the bytes of a store value.
+pub type FieldDecoder = Box<dyn Fn(&[u8]) -> String>;
+
+/// A column holding the whole value, decoded per `value_type` (big numbers as decimal strings).
+pub fn value_field(value_type: ValueType) -> FieldDecoder {
+    Box::new(move |bytes| match value_type.decode_json(bytes) {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    })
+}
+
+/// A column taken out of a proto value.
+pub fn proto_field<T: FromBytesProto>(column: impl Fn(&T) -> String + 'static) -> FieldDecoder {
+    Box::new(move |bytes| column(&<T as FromBytesProto>::from_bytes(bytes)))
+}
+
+/// Where the deltas of keys starting with a prefix go.
+pub struct TableMapping {
+    prefix: String,
+    table: String,
+    primary_key: Box<dyn Fn(&str) -> String>,
+    fields: Vec<(String, FieldDecoder)>,
+}
+
+impl TableMapping {
+    /// Keys starting with `prefix` become rows of `table`, the primary key is the
+    /// rest of the key until [`TableMapping::primary_key`] says otherwise.
+    pub fn new(prefix: &str, table: &str) -> Self {
+        let strip = prefix.to_string();
+        Self {
+            prefix: prefix.to_string(),
+            table: table.to_string(),
+            primary_key: Box::new(move |key| key.strip_prefix(strip.as_str()).unwrap_or(key).to_string()),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Builds the primary key from the full store key.
+    pub fn primary_key(mut self, primary_key: impl Fn(&str) -> String + 'static) -> Self {
+        self.primary_key = Box::new(primary_key);
+        self
+    }
+
+    /// Adds a column, in the order fields show up in the rows.
+    pub fn field(mut self, name: &str, decoder: FieldDecoder) -> Self {
+        self.fields.push((name.to_string(), decoder));
+        self
+    }
+
+    fn change(&self, delta: &StoreDelta) -> TableChange {
+        let operation = match DeltaOperation::try_from(delta.operation).unwrap_or(DeltaOperation::Unset) {
+            DeltaOperation::Create => Operation::Create,
+            DeltaOperation::Update => Operation::Update,
+            DeltaOperation::Delete => Operation::Delete,
+            DeltaOperation::Unset => Operation::Unset,
+        };
+
+        // creates have nothing to decode on the old side and deletes on the new one
+        let decode = |decoder: &FieldDecoder, bytes: &[u8], present: bool| {
+            if present { decoder(bytes) } else { String::new() }
+        };
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, decoder)| Field {
+                name: name.clone(),
+                old_value: decode(decoder, &delta.old_value, !matches!(operation, Operation::Create)),
+                new_value: decode(decoder, &delta.new_value, !matches!(operation, Operation::Delete)),
+            })
+            .collect();
+
+        TableChange {
+            table: self.table.clone(),
+            pk: (self.primary_key)(&delta.key),
+            ordinal: delta.ordinal,
+            operation: operation as i32,
+            fields,
+        }
+    }
+}
+
+/// Key prefix to table mappings, the first matching prefix wins.
+#[derive(Default)]
+pub struct DbMapping {
+    tables: Vec<TableMapping>,
+}
+
+impl DbMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn table(mut self, table: TableMapping) -> Self {
+        self.tables.push(table);
+        self
+    }
+
+    /// One row change per delta whose key is mapped, in delta order; other keys are skipped.
+    pub fn changes(&self, deltas: &[StoreDelta]) -> DatabaseChanges {
+        let table_changes = deltas
+            .iter()
+            .filter_map(|delta| {
+                let table = self.tables.iter().find(|table| delta.key.starts_with(&table.prefix))?;
+                Some(table.change(delta))
+            })
+            .collect();
+
+        DatabaseChanges { table_changes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{delta::DeltaRecorder, key, store::{MockProtoStore, MockStore}};
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    struct Pair {
+        #[prost(uint64, tag = "1")]
+        fee: u64,
+    }
+
+    fn field(name: &str, old_value: &str, new_value: &str) -> Field {
+        Field { name: name.to_string(), old_value: old_value.to_string(), new_value: new_value.to_string() }
+    }
+
+    #[test]
+    fn mapped_deltas_become_rows() {
+        let store = <MockStore as StoreNew>::new();
+        store.add(1, "pool:0xab", BigInt::from(10));
+        store.add(2, "pool:0xab", BigInt::from(5));
+        store.add(3, "other:0xab", BigInt::from(1));
+        store.delete_prefix(4, &"pool:".to_string());
+
+        let mapping = DbMapping::new().table(TableMapping::new("pool:", "pools").field("volume", value_field(ValueType::BigInt)));
+        let rows: Vec<(String, i32, Vec<Field>)> = mapping
+            .changes(&store.store_deltas(0))
+            .table_changes
+            .into_iter()
+            .map(|change| (change.pk, change.operation, change.fields))
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                ("0xab".to_string(), Operation::Create as i32, vec![field("volume", "", "10")]),
+                ("0xab".to_string(), Operation::Update as i32, vec![field("volume", "10", "15")]),
+                ("0xab".to_string(), Operation::Delete as i32, vec![field("volume", "15", "")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn proto_fields_and_custom_primary_keys() {
+        let store = <MockProtoStore<Pair> as StoreNew>::new();
+        store.set(7, "pair:0xab:0xcd", &Pair { fee: 3 });
+
+        let mapping = DbMapping::new().table(
+            TableMapping::new("pair:", "pairs")
+                .primary_key(|key| key::segment_at_owned(key.to_string(), 1))
+                .field("fee", proto_field(|pair: &Pair| pair.fee.to_string())),
+        );
+        let change = &mapping.changes(&store.store_deltas(0)).table_changes[0];
+
+        assert_eq!((change.table.as_str(), change.pk.as_str(), change.ordinal), ("pairs", "0xab", 7));
+        assert_eq!(change.fields, vec![field("fee", "", "3")]);
+    }
+}
@@ -1,17 +1,108 @@
-//Deltas -> to be implemented, from https://github.com/streamingfast/substreams-rs/blob/995a9bfcc15ebd59df63bdb2ce1b5d095d189d06/substreams/src/store.rs#L1241
-use crate::mock_store::{
-    key,
-    traits::get_value_from_bytes,
-};
+//! Deltas recorded by the mock stores.
+//!
+//! Every write on a mock store emits the `StoreDelta` the substreams runtime would,
+//! grouped by the block that was current when the write happened. A downstream
+//! module taking the store with `mode: deltas` receives exactly
+//! `Deltas::<DeltaX>::new(those deltas)`, which is what [`DeltaRecorder::deltas`] builds.
+//!
+//! ```no_run
+//! # use substreams::{prelude::*, store::{DeltaBigInt, Deltas}};
+//! # use Stores_and_Deltas::mock_store::{delta::DeltaRecorder, store::MockStore};
+//! let store = <MockStore as StoreNew>::new();
+//! store.begin_block(12);
+//! store.add(1, "volume", BigInt::from(10));
+//! store.add(2, "volume", BigInt::from(5));
+//!
+//! let deltas: Deltas<DeltaBigInt> = store.deltas(12); // Create 10, then Update 10 -> 15
+//! ```
 use substreams::{
-    prelude::{BigInt, BigDecimal},
-    pb::substreams::{
-        StoreDelta,
-        store_delta::{Operation},
-    },
-    store::{StoreAdd, StoreSetSum, Delta, DeltaBigInt, DeltaBigDecimal, DeltaBool, DeltaBytes, DeltaFloat64, DeltaInt32, DeltaInt64},
-    // key,
+    pb::substreams::StoreDelta,
+    store::{Delta, Deltas},
 };
+use crate::mock_store::{handle::StoreHandle, store::HasHandle};
+
+/// Block boundaries and the deltas recorded in each block, for every mock store.
+pub trait DeltaRecorder {
+    /// Writes made after this call are recorded under `block`, stores start at block 0.
+    fn begin_block(&self, block: u64);
+
+    fn current_block(&self) -> u64;
+
+    /// The raw deltas emitted during `block`, in write order.
+    fn store_deltas(&self, block: u64) -> Vec<StoreDelta>;
+
+    /// (block number, deltas) of every block that had writes, in block order.
+    fn recorded_deltas(&self) -> Vec<(u64, Vec<StoreDelta>)>;
+
+    /// Every prefix passed to `delete_prefix`, in call order.
+    fn deleted_prefixes(&self) -> Vec<String>;
+
+    /// What a `mode: deltas` input on this store would receive for `block`.
+    fn deltas<D: Delta + From<StoreDelta>>(&self, block: u64) -> Deltas<D> {
+        Deltas::new(self.store_deltas(block))
+    }
+}
+
+impl<S: HasHandle> DeltaRecorder for S {
+    fn begin_block(&self, block: u64) {
+        self.handle().begin_block(block);
+    }
+
+    fn current_block(&self) -> u64 {
+        self.handle().current_block()
+    }
+
+    fn store_deltas(&self, block: u64) -> Vec<StoreDelta> {
+        self.handle().deltas_at(block)
+    }
+
+    fn recorded_deltas(&self) -> Vec<(u64, Vec<StoreDelta>)> {
+        self.handle().recorded_deltas()
+    }
+
+    fn deleted_prefixes(&self) -> Vec<String> {
+        self.handle().deleted_prefixes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::{
+        pb::substreams::store_delta::Operation,
+        prelude::*,
+        store::{DeltaBigInt, DeltaExt},
+    };
+    use super::*;
+    use crate::mock_store::store::MockStore;
+
+    #[test]
+    fn deltas_are_recorded_per_block() {
+        let store = <MockStore as StoreNew>::new();
+        store.begin_block(12);
+        store.add(1, "volume", BigInt::from(10));
+        store.add(2, "volume", BigInt::from(5));
+        store.begin_block(13);
+        store.add(1, "volume", BigInt::from(1));
+
+        let deltas: Deltas<DeltaBigInt> = store.deltas(12);
+        let changes: Vec<_> = deltas
+            .iter()
+            .map(|delta| (delta.operation, delta.old_value.clone(), delta.new_value.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (Operation::Create, BigInt::zero(), BigInt::from(10)),
+                (Operation::Update, BigInt::from(10), BigInt::from(15)),
+            ]
+        );
+        assert_eq!(store.recorded_deltas().iter().map(|(block, _)| *block).collect::<Vec<_>>(), vec![12, 13]);
+        assert!(store.store_deltas(14).is_empty());
+        assert_eq!(store.deltas::<DeltaBigInt>(13).iter().key_first_segment_eq("volume").count(), 1);
+    }
+}
+
+//Deltas come from https://github.com/streamingfast/substreams-rs/blob/995a9bfcc15ebd59df63bdb2ce1b5d095d189d06/substreams/src/store.rs#L1241
 
 //Mock Deltas are a non issue because they are basically a Vec<Deltas> that you pass in to a 
 //Delta::new() constructor, it has its own custom iterators for you and everythng you need outside the box
@@ -0,0 +1,276 @@
+//! Filtering, grouping and collapsing of recorded deltas.
+//!
+//! `mode: deltas` handlers keep filtering deltas by key and folding several
+//! writes to the same key into one net change by hand. These work on the raw
+//! `StoreDelta`s the stores and harnesses hand out and return owned lists, so
+//! they chain and the result can still go through `Deltas::<DeltaX>::new`.
+//!
+//! ```no_run
+//! # use substreams::{pb::substreams::store_delta::Operation, prelude::*, store::{DeltaBigInt, Deltas}};
+//! # use Stores_and_Deltas::mock_store::{delta::DeltaRecorder, delta_ops::StoreDeltasExt, store::MockStore};
+//! # let store = <MockStore as StoreNew>::new();
+//! let net = store
+//!     .store_deltas(12)
+//!     .with_prefix("pool:")
+//!     .with_operation(Operation::Update)
+//!     .collapse();
+//! let deltas: Deltas<DeltaBigInt> = Deltas::new(net);
+//! ```
+//!
+//! Per-block lists, like `HarnessRun::deltas`, can be squashed across blocks the
+//! way a sink catching up only writes the net change of each key:
+//!
+//! ```no_run
+//! # use substreams::pb::substreams::StoreDelta;
+//! # use Stores_and_Deltas::mock_store::{delta_ops::BlockDeltasExt, harness::HarnessRun, store::MockStore};
+//! # struct Sink;
+//! # impl Sink { fn write(&mut self, _block: u64, _deltas: &[StoreDelta]) {} }
+//! # fn flush(run: HarnessRun<MockStore>, mut sink: Sink) {
+//! for flush in run.deltas.squash_every(100) {
+//!     sink.write(flush.block, &flush.deltas);
+//! }
+//! # }
+//! ```
+use std::collections::{BTreeMap, HashMap};
+use substreams::pb::substreams::{store_delta::Operation, StoreDelta};
+use crate::mock_store::key;
+
+pub trait StoreDeltasExt {
+    /// Deltas whose key starts with `prefix`.
+    fn with_prefix(&self, prefix: &str) -> Vec<StoreDelta>;
+
+    /// Deltas whose key has `value` as its segment at `index`, see the `key` module.
+    fn with_segment(&self, index: usize, value: &str) -> Vec<StoreDelta>;
+
+    fn with_operation(&self, operation: Operation) -> Vec<StoreDelta>;
+
+    /// Deltas of each key, in their original order.
+    fn group_by_key(&self) -> BTreeMap<String, Vec<StoreDelta>>;
+
+    /// Deltas grouped by their key segment at `index`, keys without that segment are left out.
+    fn group_by_segment(&self, index: usize) -> BTreeMap<String, Vec<StoreDelta>>;
+
+    /// One net delta per key, sorted by the ordinal it carries so the result
+    /// replays in ordinal order like any other delta list.
+    ///
+    /// The net delta has the old value of the first delta, the new value and
+    /// ordinal of the last one, the write that settled the key. Its operation depends on whether the key existed
+    /// before the first delta and after the last one: `Create`, `Delete`, `Update`,
+    /// or nothing at all for a key created then deleted.
+    fn collapse(&self) -> Vec<StoreDelta>;
+}
+
+impl StoreDeltasExt for [StoreDelta] {
+    fn with_prefix(&self, prefix: &str) -> Vec<StoreDelta> {
+        self.iter().filter(|delta| delta.key.starts_with(prefix)).cloned().collect()
+    }
+
+    fn with_segment(&self, index: usize, value: &str) -> Vec<StoreDelta> {
+        self.iter()
+            .filter(|delta| key::try_segment_at(&delta.key, index) == Some(value))
+            .cloned()
+            .collect()
+    }
+
+    fn with_operation(&self, operation: Operation) -> Vec<StoreDelta> {
+        self.iter().filter(|delta| delta.operation == operation as i32).cloned().collect()
+    }
+
+    fn group_by_key(&self) -> BTreeMap<String, Vec<StoreDelta>> {
+        let mut groups: BTreeMap<String, Vec<StoreDelta>> = BTreeMap::new();
+        for delta in self {
+            groups.entry(delta.key.clone()).or_default().push(delta.clone());
+        }
+        groups
+    }
+
+    fn group_by_segment(&self, index: usize) -> BTreeMap<String, Vec<StoreDelta>> {
+        let mut groups: BTreeMap<String, Vec<StoreDelta>> = BTreeMap::new();
+        for delta in self {
+            if let Some(segment) = key::try_segment_at(&delta.key, index) {
+                groups.entry(segment.to_string()).or_default().push(delta.clone());
+            }
+        }
+        groups
+    }
+
+    fn collapse(&self) -> Vec<StoreDelta> {
+        // key -> (first delta, last delta), plus the order keys showed up in for ties
+        let mut order: Vec<&str> = Vec::new();
+        let mut ends: HashMap<&str, (&StoreDelta, &StoreDelta)> = HashMap::new();
+        for delta in self {
+            ends.entry(&delta.key)
+                .and_modify(|(_, last)| *last = delta)
+                .or_insert_with(|| {
+                    order.push(&delta.key);
+                    (delta, delta)
+                });
+        }
+
+        let mut net: Vec<StoreDelta> = order
+            .into_iter()
+            .filter_map(|key| {
+                let (first, last) = ends[key];
+                net_delta(first, last)
+            })
+            .collect();
+        net.sort_by_key(|delta| delta.ordinal);
+        net
+    }
+}
+
+// the single delta going from before `first` to after `last`, None when the key
+// neither existed before nor after
+fn net_delta(first: &StoreDelta, last: &StoreDelta) -> Option<StoreDelta> {
+    let existed_before = first.operation != Operation::Create as i32;
+    let exists_after = last.operation != Operation::Delete as i32;
+
+    let operation = match (existed_before, exists_after) {
+        (false, false) => return None,
+        (false, true) => Operation::Create,
+        (true, false) => Operation::Delete,
+        (true, true) => Operation::Update,
+    };
+
+    Some(StoreDelta {
+        operation: operation as i32,
+        ordinal: last.ordinal,
+        key: last.key.clone(),
+        old_value: if existed_before { first.old_value.clone() } else { Vec::new() },
+        new_value: if exists_after { last.new_value.clone() } else { Vec::new() },
+    })
+}
+
+/// The net deltas of a range of blocks, tagged with the last block of the range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SquashedDeltas {
+    pub block: u64,
+    pub deltas: Vec<StoreDelta>,
+}
+
+/// Squashing of (block number, deltas) lists.
+pub trait BlockDeltasExt {
+    /// [`StoreDeltasExt::collapse`] over every block, in block order: keys created
+    /// and deleted within the range are dropped, updates merged into one. None
+    /// when there are no blocks.
+    fn squash(&self) -> Option<SquashedDeltas>;
+
+    /// [`BlockDeltasExt::squash`] over every range of `n` block numbers, `0..n`,
+    /// `n..2n` and so on, in block order. Ranges without any block are skipped, so
+    /// lists only holding the blocks that had deltas flush at the same points as
+    /// dense ones.
+    fn squash_every(&self, n: usize) -> Vec<SquashedDeltas>;
+}
+
+impl BlockDeltasExt for [(u64, Vec<StoreDelta>)] {
+    fn squash(&self) -> Option<SquashedDeltas> {
+        let mut blocks: Vec<&(u64, Vec<StoreDelta>)> = self.iter().collect();
+        blocks.sort_by_key(|(block_num, _)| *block_num);
+
+        let block = blocks.last()?.0;
+        let deltas: Vec<StoreDelta> = blocks.into_iter().flat_map(|(_, deltas)| deltas.iter().cloned()).collect();
+
+        Some(SquashedDeltas { block, deltas: deltas.collapse() })
+    }
+
+    fn squash_every(&self, n: usize) -> Vec<SquashedDeltas> {
+        assert!(n > 0, "cannot squash every 0 blocks");
+        let mut blocks: Vec<(u64, Vec<StoreDelta>)> = self.to_vec();
+        blocks.sort_by_key(|(block_num, _)| *block_num);
+
+        blocks
+            .chunk_by(|a, b| a.0 / n as u64 == b.0 / n as u64)
+            .filter_map(|range| range.squash())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(operation: Operation, ordinal: u64, key: &str, old_value: &[u8], new_value: &[u8]) -> StoreDelta {
+        StoreDelta {
+            operation: operation as i32,
+            ordinal,
+            key: key.to_string(),
+            old_value: old_value.to_vec(),
+            new_value: new_value.to_vec(),
+        }
+    }
+
+    #[test]
+    fn filters_and_groups_keep_delta_order() {
+        let deltas = [
+            delta(Operation::Create, 1, "pool:0xab:volume", b"", b"1"),
+            delta(Operation::Create, 2, "token:0xcd", b"", b"1"),
+            delta(Operation::Update, 3, "pool:0xef:volume", b"1", b"2"),
+            delta(Operation::Update, 4, "pool:0xab:volume", b"1", b"3"),
+        ];
+        let keys = |deltas: &[StoreDelta]| deltas.iter().map(|d| d.ordinal).collect::<Vec<_>>();
+
+        assert_eq!(keys(&deltas.with_prefix("pool:")), vec![1, 3, 4]);
+        assert_eq!(keys(&deltas.with_segment(1, "0xab")), vec![1, 4]);
+        assert_eq!(keys(&deltas.with_prefix("pool:").with_operation(Operation::Update)), vec![3, 4]);
+        assert_eq!(deltas.group_by_key().keys().collect::<Vec<_>>(), vec!["pool:0xab:volume", "pool:0xef:volume", "token:0xcd"]);
+        assert_eq!(keys(&deltas.group_by_segment(2)["volume"]), vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn collapse_nets_out_every_key() {
+        let deltas = [
+            delta(Operation::Update, 1, "updated", b"1", b"2"),
+            delta(Operation::Create, 2, "created", b"", b"1"),
+            delta(Operation::Create, 3, "transient", b"", b"1"),
+            delta(Operation::Update, 4, "updated", b"2", b"3"),
+            delta(Operation::Delete, 5, "transient", b"1", b""),
+            delta(Operation::Delete, 6, "deleted", b"4", b""),
+            delta(Operation::Update, 7, "created", b"1", b"5"),
+            // deleted then written again within the range is an update of the old value
+            delta(Operation::Delete, 8, "recreated", b"6", b""),
+            delta(Operation::Create, 9, "recreated", b"", b"7"),
+        ];
+
+        assert_eq!(
+            deltas.collapse(),
+            vec![
+                delta(Operation::Update, 4, "updated", b"1", b"3"),
+                delta(Operation::Delete, 6, "deleted", b"4", b""),
+                delta(Operation::Create, 7, "created", b"", b"5"),
+                delta(Operation::Update, 9, "recreated", b"6", b"7"),
+            ]
+        );
+    }
+
+    #[test]
+    fn squash_merges_blocks_in_block_order() {
+        let blocks = [
+            (2, vec![delta(Operation::Update, 1, "a", b"1", b"2")]),
+            (1, vec![delta(Operation::Create, 1, "a", b"", b"1"), delta(Operation::Create, 2, "b", b"", b"1")]),
+            (3, vec![delta(Operation::Delete, 1, "b", b"1", b"")]),
+        ];
+
+        let squashed = blocks.squash().unwrap();
+        assert_eq!(squashed.block, 3);
+        assert_eq!(squashed.deltas, vec![delta(Operation::Create, 1, "a", b"", b"2")]);
+        assert_eq!(Vec::<(u64, Vec<StoreDelta>)>::new().squash(), None);
+    }
+
+    #[test]
+    fn squash_every_chunks_by_block_number() {
+        // sparse, only the blocks that had deltas
+        let blocks = [
+            (3, vec![delta(Operation::Create, 1, "a", b"", b"1")]),
+            (9, vec![delta(Operation::Update, 1, "a", b"1", b"2")]),
+            (10, vec![delta(Operation::Update, 1, "a", b"2", b"3")]),
+            (25, vec![delta(Operation::Update, 1, "a", b"3", b"4")]),
+        ];
+
+        let flushes: Vec<(u64, Vec<u8>)> = blocks
+            .squash_every(10)
+            .into_iter()
+            .map(|flush| (flush.block, flush.deltas[0].new_value.clone()))
+            .collect();
+        assert_eq!(flushes, vec![(9, b"2".to_vec()), (10, b"3".to_vec()), (25, b"4".to_vec())]);
+    }
+}
@@ -0,0 +1,172 @@
+//! Readable dumps of a store's content.
+//!
+//! `Debug` on a store prints the raw `Vec<u8>` histories, which says nothing when
+//! a test fails. A dump lists the keys sorted, every version with its ordinal and
+//! the value decoded through [`FormatValue`]:
+//!
+//! ```text
+//! MockStore<BigInt>, 2 keys
+//! "pair:0xab"
+//!   #1 3
+//!   #5 4
+//! "volume"
+//!   #1 1200
+//! ```
+//!
+//! ```no_run
+//! # use Stores_and_Deltas::mock_store::store::MockStore;
+//! # let store = MockStore::default();
+//! println!("{}", store.dump().prefix("pair:").last_only());
+//! ```
+//!
+//! Proto values show up through their `Debug` impl, or in text format once the
+//! dump is given the package's descriptors, see the `text_format` module.
+use std::fmt;
+use crate::mock_store::{
+    format::FormatValue,
+    handle::StoreHandle,
+    store::{BaseMockArrayStore, BaseMockProtoStore, BaseMockStore, HasHandle},
+    text_format::{self, DescriptorPool},
+    traits::FromBytesProto,
+};
+
+/// A store ready to be printed, see the module docs.
+pub struct StoreDump<'a, S> {
+    store: &'a S,
+    name: &'static str,
+    prefix: String,
+    last_only: bool,
+    descriptors: Option<&'a DescriptorPool>,
+}
+
+impl<'a, S> StoreDump<'a, S> {
+    /// Only keys starting with `prefix`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Only the last version of each key, on the same line as the key.
+    pub fn last_only(mut self) -> Self {
+        self.last_only = true;
+        self
+    }
+
+    /// Proto values in text format, for stores declared with a `proto:` value type
+    /// that `pool` has the message of.
+    pub fn descriptors(mut self, pool: &'a DescriptorPool) -> Self {
+        self.descriptors = Some(pool);
+        self
+    }
+}
+
+impl<S: HasHandle + FormatValue> fmt::Display for StoreDump<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // copy the histories out, formatting protos shouldn't happen under the lock
+        let mut entries: Vec<_> = {
+            let state = self.store.handle().read();
+            state
+                .kv
+                .iter()
+                .filter(|(key, _)| key.starts_with(&self.prefix))
+                .map(|(key, versions)| (key.clone(), versions.clone()))
+                .collect()
+        };
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let noun = if entries.len() == 1 { "key" } else { "keys" };
+        write!(f, "{}<{}>, {} {}", self.name, self.store.value_label(), entries.len(), noun)?;
+        if !self.prefix.is_empty() {
+            write!(f, " with prefix {:?}", self.prefix)?;
+        }
+
+        for (key, versions) in entries {
+            if self.last_only {
+                if let Some((ord, bytes)) = versions.last() {
+                    write!(f, "\n{:?} #{} {}", key, ord, self.value(bytes))?;
+                }
+                continue;
+            }
+
+            write!(f, "\n{:?}", key)?;
+            for (ord, bytes) in versions.iter() {
+                write!(f, "\n  #{} {}", ord, self.value(bytes))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: HasHandle + FormatValue> StoreDump<'_, S> {
+    fn value(&self, bytes: &[u8]) -> String {
+        text_format::format_value(self.store, self.descriptors, bytes)
+    }
+}
+
+impl<H: StoreHandle> BaseMockStore<H> {
+    pub fn dump(&self) -> StoreDump<'_, Self> {
+        StoreDump { store: self, name: "MockStore", prefix: String::new(), last_only: false, descriptors: None }
+    }
+}
+
+impl<T: FromBytesProto, H: StoreHandle> BaseMockProtoStore<T, H> {
+    pub fn dump(&self) -> StoreDump<'_, Self> {
+        StoreDump { store: self, name: "MockProtoStore", prefix: String::new(), last_only: false, descriptors: None }
+    }
+}
+
+impl<H: StoreHandle> BaseMockArrayStore<H> {
+    pub fn dump(&self) -> StoreDump<'_, Self> {
+        StoreDump { store: self, name: "MockArrayStore", prefix: String::new(), last_only: false, descriptors: None }
+    }
+}
+
+impl<H: StoreHandle> fmt::Display for BaseMockStore<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.dump().fmt(f)
+    }
+}
+
+impl<T: FromBytesProto + fmt::Debug, H: StoreHandle> fmt::Display for BaseMockProtoStore<T, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.dump().fmt(f)
+    }
+}
+
+impl<H: StoreHandle> fmt::Display for BaseMockArrayStore<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.dump().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use crate::mock_store::{store::{MockStore, StoreConfig}, value_type::ValueType};
+
+    fn store() -> MockStore {
+        let store = <MockStore as StoreNew>::new();
+        store.configure(None, Some(ValueType::BigInt));
+        store.set(1, "volume", &BigInt::from(1200));
+        store.set(1, "pair:0xab", &BigInt::from(3));
+        store.set(5, "pair:0xab", &BigInt::from(4));
+        store
+    }
+
+    #[test]
+    fn dumps_every_version_sorted_by_key() {
+        assert_eq!(
+            store().to_string(),
+            "MockStore<BigInt>, 2 keys\n\"pair:0xab\"\n  #1 3\n  #5 4\n\"volume\"\n  #1 1200"
+        );
+    }
+
+    #[test]
+    fn dumps_last_versions_under_a_prefix() {
+        assert_eq!(
+            store().dump().prefix("pair:").last_only().to_string(),
+            "MockStore<BigInt>, 1 key with prefix \"pair:\"\n\"pair:0xab\" #5 4"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Turns recorded store deltas into graph-node entity changes.
+//!
+//! Subgraph sinks consume a `graph_out` module emitting `EntityChanges`. Stores
+//! feeding it usually keep one field of one entity per key, `<entity>:<id>:<field>`
+//! (`pool:0xab:volume`), so the mapping is driven by the key segments, read with
+//! the `key` module: which segment names the entity, which one is the id and which
+//! one the field. Deltas of the same entity are merged into one change.
+//!
+//! ```no_run
+//! # use substreams::prelude::*;
+//! # use Stores_and_Deltas::mock_store::{delta::DeltaRecorder, entity_changes::EntityMapping, store::MockStore, value_type::ValueType};
+//! # let store = <MockStore as StoreNew>::new();
+//! let mapping = EntityMapping::new(ValueType::BigInt)
+//!     .entity("pool", "Pool")
+//!     .field_type("name", ValueType::String);
+//!
+//! let changes = mapping.changes(&store.store_deltas(12));
+//! ```
+//!
+//! The messages mirror `sf.substreams.entity.v1` (same field tags, without the
+//! timestamp and array values) so `graph_out` output decodes into them.
+use std::collections::HashMap;
+use substreams::pb::substreams::{store_delta::Operation as DeltaOperation, StoreDelta};
+use crate::mock_store::{key, value_type::ValueType};
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EntityChanges {
+    #[prost(message, repeated, tag = "5")]
+    pub entity_changes: Vec<EntityChange>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EntityChange {
+    #[prost(string, tag = "1")]
+    pub entity: String,
+    #[prost(string, tag = "2")]
+    pub id: String,
+    #[prost(uint64, tag = "3")]
+    pub ordinal: u64,
+    #[prost(enumeration = "Operation", tag = "4")]
+    pub operation: i32,
+    #[prost(message, repeated, tag = "5")]
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Operation {
+    Unspecified = 0,
+    Create = 1,
+    Update = 2,
+    Delete = 3,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Field {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, optional, tag = "3")]
+    pub new_value: Option<Value>,
+    #[prost(message, optional, tag = "5")]
+    pub old_value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Value {
+    #[prost(oneof = "value::Typed", tags = "1, 2, 3, 4, 5, 6")]
+    pub typed: Option<value::Typed>,
+}
+
+pub mod value {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Typed {
+        #[prost(int32, tag = "1")]
+        Int32(i32),
+        #[prost(string, tag = "2")]
+        Bigdecimal(String),
+        #[prost(string, tag = "3")]
+        Bigint(String),
+        #[prost(string, tag = "4")]
+        String(String),
+        #[prost(bytes, tag = "5")]
+        Bytes(Vec<u8>),
+        #[prost(bool, tag = "6")]
+        Bool(bool),
+    }
+}
+
+impl Value {
+    /// Reads store bytes as an entity value, ints become `BigInt` columns and
+    /// floats `BigDecimal` ones since entities have no 64 bit types.
+    pub fn from_store_bytes(value_type: &ValueType, bytes: &[u8]) -> Self {
+        let text = || String::from_utf8_lossy(bytes).into_owned();
+        let typed = match value_type {
+            ValueType::BigInt | ValueType::Int64 => value::Typed::Bigint(text()),
+            ValueType::BigDecimal | ValueType::Float64 => value::Typed::Bigdecimal(text()),
+            ValueType::String => value::Typed::String(text()),
+            ValueType::Bytes | ValueType::Proto(_) => value::Typed::Bytes(bytes.to_vec()),
+        };
+        Value { typed: Some(typed) }
+    }
+}
+
+/// Which key segments make up an entity change, see the module docs.
+pub struct EntityMapping {
+    value_type: ValueType,
+    entity_segment: usize,
+    id_segment: usize,
+    field_segment: usize,
+    /// entity segment value -> entity name
+    entities: Vec<(String, String)>,
+    field_types: HashMap<String, ValueType>,
+}
+
+impl EntityMapping {
+    /// Keys laid out as `<entity>:<id>:<field>`, values read as `value_type`.
+    pub fn new(value_type: ValueType) -> Self {
+        Self {
+            value_type,
+            entity_segment: 0,
+            id_segment: 1,
+            field_segment: 2,
+            entities: Vec::new(),
+            field_types: HashMap::new(),
+        }
+    }
+
+    /// Keys whose entity segment is `segment` become `name` entities, keys of
+    /// unmapped entities are skipped.
+    pub fn entity(mut self, segment: &str, name: &str) -> Self {
+        self.entities.push((segment.to_string(), name.to_string()));
+        self
+    }
+
+    /// Where the entity, id and field names are in the keys.
+    pub fn segments(mut self, entity: usize, id: usize, field: usize) -> Self {
+        self.entity_segment = entity;
+        self.id_segment = id;
+        self.field_segment = field;
+        self
+    }
+
+    /// Overrides the value type for one field.
+    pub fn field_type(mut self, field: &str, value_type: ValueType) -> Self {
+        self.field_types.insert(field.to_string(), value_type);
+        self
+    }
+
+    /// One change per entity, in the order entities first show up in `deltas`.
+    ///
+    /// A field written several times keeps its first old value and its last new
+    /// one. The change is a `Create` when every field started with a create (the
+    /// entity didn't exist before), a `Delete` when every field ended deleted, an
+    /// `Update` otherwise.
+    ///
+    /// Fields created then deleted within `deltas` are left out, like
+    /// `StoreDeltasExt::collapse` drops such keys, and so are entities left
+    /// without fields: graph-node never knew about them.
+    pub fn changes(&self, deltas: &[StoreDelta]) -> EntityChanges {
+        let mut changes: Vec<EntityChange> = Vec::new();
+        // (entity, id) -> (index in changes, every field started with a create)
+        let mut seen: HashMap<(String, String), (usize, bool)> = HashMap::new();
+
+        for delta in deltas {
+            let Some((entity, id, field)) = self.parse_key(&delta.key) else { continue };
+            let operation = DeltaOperation::try_from(delta.operation).unwrap_or(DeltaOperation::Unset);
+            let value_type = self.field_types.get(field).unwrap_or(&self.value_type);
+
+            let (idx, created) = seen.entry((entity.clone(), id.to_string())).or_insert_with(|| {
+                changes.push(EntityChange {
+                    entity: entity.clone(),
+                    id: id.to_string(),
+                    ordinal: delta.ordinal,
+                    operation: Operation::Unspecified as i32,
+                    fields: Vec::new(),
+                });
+                (changes.len() - 1, true)
+            });
+            let change = &mut changes[*idx];
+
+            let new_value = match operation {
+                DeltaOperation::Delete => None,
+                _ => Some(Value::from_store_bytes(value_type, &delta.new_value)),
+            };
+            match change.fields.iter_mut().find(|f| f.name == field) {
+                Some(existing) => existing.new_value = new_value,
+                None => {
+                    *created &= operation == DeltaOperation::Create;
+                    change.fields.push(Field {
+                        name: field.to_string(),
+                        old_value: match operation {
+                            DeltaOperation::Create => None,
+                            _ => Some(Value::from_store_bytes(value_type, &delta.old_value)),
+                        },
+                        new_value,
+                    });
+                }
+            }
+
+            change.operation = if change.fields.iter().all(|f| f.new_value.is_none()) {
+                Operation::Delete
+            } else if *created {
+                Operation::Create
+            } else {
+                Operation::Update
+            } as i32;
+        }
+
+        for change in &mut changes {
+            change.fields.retain(|f| f.old_value.is_some() || f.new_value.is_some());
+        }
+        changes.retain(|change| !change.fields.is_empty());
+
+        EntityChanges { entity_changes: changes }
+    }
+
+    // (entity name, id, field), None for keys of unmapped entities or with too few segments
+    fn parse_key<'k>(&self, store_key: &'k str) -> Option<(String, &'k str, &'k str)> {
+        let segment = key::try_segment_at(store_key, self.entity_segment)?;
+        let (_, entity) = self.entities.iter().find(|(s, _)| s == segment)?;
+        let id = key::try_segment_at(store_key, self.id_segment)?;
+        let field = key::try_segment_at(store_key, self.field_segment)?;
+        Some((entity.clone(), id, field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{delta::DeltaRecorder, store::MockStore};
+
+    fn mapping() -> EntityMapping {
+        EntityMapping::new(ValueType::BigInt)
+            .entity("pool", "Pool")
+            .field_type("name", ValueType::String)
+    }
+
+    fn bigint(value: &str) -> Option<Value> {
+        Some(Value { typed: Some(value::Typed::Bigint(value.to_string())) })
+    }
+
+    #[test]
+    fn fields_of_an_entity_are_merged_into_one_change() {
+        let store = <MockStore as StoreNew>::new();
+        store.begin_block(1);
+        store.add(1, "pool:0xab:volume", BigInt::from(10));
+        store.set(2, "pool:0xab:name", &"WETH/USDC".to_string());
+        store.add(3, "pool:0xab:volume", BigInt::from(5));
+        store.add(4, "token:0xcd:volume", BigInt::from(1));
+
+        let changes = mapping().changes(&store.store_deltas(1)).entity_changes;
+        assert_eq!(changes.len(), 1);
+        let change = &changes[0];
+        assert_eq!((change.entity.as_str(), change.id.as_str(), change.ordinal), ("Pool", "0xab", 1));
+        assert_eq!(change.operation, Operation::Create as i32);
+        assert_eq!(
+            change.fields,
+            vec![
+                Field { name: "volume".to_string(), old_value: None, new_value: bigint("15") },
+                Field {
+                    name: "name".to_string(),
+                    old_value: None,
+                    new_value: Some(Value { typed: Some(value::Typed::String("WETH/USDC".to_string())) }),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_entities_are_updated_then_deleted() {
+        let store = <MockStore as StoreNew>::new();
+        store.add(1, "pool:0xab:volume", BigInt::from(10));
+        store.begin_block(2);
+        store.add(1, "pool:0xab:volume", BigInt::from(5));
+        store.begin_block(3);
+        store.delete_prefix(1, &"pool:0xab:".to_string());
+
+        let update = &mapping().changes(&store.store_deltas(2)).entity_changes[0];
+        assert_eq!(update.operation, Operation::Update as i32);
+        assert_eq!(update.fields[0].old_value, bigint("10"));
+
+        let delete = &mapping().changes(&store.store_deltas(3)).entity_changes[0];
+        assert_eq!(delete.operation, Operation::Delete as i32);
+        assert_eq!(delete.fields[0].new_value, None);
+    }
+
+    #[test]
+    fn entities_created_then_deleted_are_left_out() {
+        let store = <MockStore as StoreNew>::new();
+        store.begin_block(1);
+        store.add(1, "pool:0xab:volume", BigInt::from(10));
+        store.set(2, "pool:0xab:name", &"WETH/USDC".to_string());
+        store.add(3, "pool:0xcd:volume", BigInt::from(1));
+        store.add(4, "pool:0xcd:fees", BigInt::from(1));
+        store.delete_prefix(5, &"pool:0xab:".to_string());
+        store.delete_prefix(6, &"pool:0xcd:fees".to_string());
+
+        let changes = mapping().changes(&store.store_deltas(1)).entity_changes;
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].id.as_str(), changes[0].operation), ("0xcd", Operation::Create as i32));
+        assert_eq!(changes[0].fields.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(), ["volume"]);
+    }
+}
@@ -0,0 +1,396 @@
+//! Small in-process executor for a graph of map and store modules.
+//!
+//! Real packages chain maps into stores into more maps, the Go runtime schedules
+//! them per block in dependency order. This does the same with mock stores so a
+//! whole package can be exercised from a test:
+//!
+//! ```no_run
+//! # use substreams::store::{DeltaBigInt, Deltas};
+//! # use Stores_and_Deltas::mock_store::{executor::{ModuleExecutor, ModuleInput}, store::MockStore};
+//! # #[derive(Clone, PartialEq, prost::Message)]
+//! # struct Block {}
+//! # #[derive(Clone, PartialEq, prost::Message)]
+//! # struct Transfers {}
+//! # #[derive(Clone, PartialEq, prost::Message)]
+//! # struct Changes {}
+//! # fn map_transfers(_block: Block) -> Transfers { Transfers {} }
+//! # fn store_balances(_transfers: Transfers, _store: MockStore) {}
+//! # fn map_changes(_deltas: Deltas<DeltaBigInt>) -> Changes { Changes {} }
+//! # let blocks: Vec<Block> = Vec::new();
+//! let mut executor = ModuleExecutor::<Block>::new();
+//! executor
+//!     .map("map_transfers", vec![ModuleInput::Source], |inputs| {
+//!         Some(map_transfers(inputs.source().clone()))
+//!     })
+//!     .store("store_balances", vec![ModuleInput::map("map_transfers")], |inputs, store: &MockStore| {
+//!         store_balances(inputs.map(0).unwrap(), store.clone())
+//!     })
+//!     .map("map_changes", vec![ModuleInput::store_deltas("store_balances")], |inputs| {
+//!         Some(map_changes(inputs.deltas::<DeltaBigInt>(0)))
+//!     });
+//!
+//! executor.starting_at(100).run(&blocks);
+//! let changes: Option<Changes> = executor.map_output("map_changes", 101);
+//! ```
+//!
+//! Inputs are looked up by their position in the module's declared inputs, like
+//! the `idx` the runtime passes to `StoreGet::new`.
+use std::collections::{BTreeMap, HashMap};
+use prost::Message;
+use substreams::{
+    pb::substreams::{module::input::store::Mode, StoreDelta},
+    store::{Delta, Deltas},
+};
+use crate::mock_store::{
+    handle::{Local, StoreHandle},
+    proto,
+    store::HasHandle,
+    view::ReadOnlyStore,
+};
+
+/// Declared input of a module, same three kinds as in a substreams manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleInput {
+    Source,
+    Map(String),
+    Store { module_name: String, mode: Mode },
+}
+
+impl ModuleInput {
+    pub fn map(module_name: &str) -> Self {
+        ModuleInput::Map(module_name.to_string())
+    }
+
+    pub fn store_get(module_name: &str) -> Self {
+        ModuleInput::Store { module_name: module_name.to_string(), mode: Mode::Get }
+    }
+
+    pub fn store_deltas(module_name: &str) -> Self {
+        ModuleInput::Store { module_name: module_name.to_string(), mode: Mode::Deltas }
+    }
+
+    fn module_name(&self) -> Option<&str> {
+        match self {
+            ModuleInput::Source => None,
+            ModuleInput::Map(module_name) => Some(module_name),
+            ModuleInput::Store { module_name, .. } => Some(module_name),
+        }
+    }
+}
+
+type MapHandler<B> = Box<dyn FnMut(&ModuleInputs<B>) -> Option<Vec<u8>>>;
+type StoreHandler<B> = Box<dyn FnMut(&ModuleInputs<B>, &Local)>;
+
+enum ModuleKind<B> {
+    Map(MapHandler<B>),
+    Store { handler: StoreHandler<B>, handle: Local },
+}
+
+struct Module<B> {
+    name: String,
+    inputs: Vec<ModuleInput>,
+    kind: ModuleKind<B>,
+}
+
+/// What a module's inputs resolve to for the block being processed.
+enum ResolvedInput {
+    Source,
+    Map(Option<Vec<u8>>),
+    Store { handle: Local, mode: Mode },
+}
+
+/// Inputs handed to a module handler for one block.
+pub struct ModuleInputs<'a, B> {
+    block_num: u64,
+    source: &'a B,
+    inputs: Vec<ResolvedInput>,
+}
+
+impl<B> ModuleInputs<'_, B> {
+    pub fn block_num(&self) -> u64 {
+        self.block_num
+    }
+
+    pub fn source(&self) -> &B {
+        self.source
+    }
+
+    /// Decoded output of the map declared at `idx`, `None` if it skipped this block.
+    pub fn map<O: Message + Default>(&self, idx: usize) -> Option<O> {
+        match self.input(idx) {
+            ResolvedInput::Map(output) => output.as_ref().map(|bytes| {
+                proto::decode(bytes).unwrap_or_else(|e| panic!("cannot decode map input {}: {}", idx, e))
+            }),
+            _ => panic!("input {} is not a map input", idx),
+        }
+    }
+
+    /// A read-only view of the store declared at `idx`, for `mode: get` inputs.
+    pub fn store<S: HasHandle<Handle = Local> + Clone>(&self, idx: usize) -> ReadOnlyStore<S> {
+        match self.input(idx) {
+            ResolvedInput::Store { handle, mode: Mode::Get } => ReadOnlyStore::of(&S::from_handle(handle.clone())),
+            _ => panic!("input {} is not a store input in get mode", idx),
+        }
+    }
+
+    /// Raw deltas of the store declared at `idx` for the current block.
+    pub fn store_deltas(&self, idx: usize) -> Vec<StoreDelta> {
+        match self.input(idx) {
+            ResolvedInput::Store { handle, mode: Mode::Deltas } => handle.deltas_at(self.block_num),
+            _ => panic!("input {} is not a store input in deltas mode", idx),
+        }
+    }
+
+    /// What a `mode: deltas` input at `idx` receives for the current block.
+    pub fn deltas<D: Delta + From<StoreDelta>>(&self, idx: usize) -> Deltas<D> {
+        Deltas::new(self.store_deltas(idx))
+    }
+
+    fn input(&self, idx: usize) -> &ResolvedInput {
+        self.inputs
+            .get(idx)
+            .unwrap_or_else(|| panic!("module has no input at index {}", idx))
+    }
+}
+
+/// Runs map and store modules in dependency order, block by block.
+pub struct ModuleExecutor<B> {
+    modules: Vec<Module<B>>,
+    start_block: u64,
+    /// module name -> block number -> encoded output
+    map_outputs: HashMap<String, BTreeMap<u64, Vec<u8>>>,
+}
+
+impl<B: Message> Default for ModuleExecutor<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Message> ModuleExecutor<B> {
+    pub fn new() -> Self {
+        Self {
+            modules: Vec::new(),
+            start_block: 0,
+            map_outputs: HashMap::new(),
+        }
+    }
+
+    /// Adds a map module, returning `None` from the handler skips the block.
+    pub fn map<O, F>(&mut self, name: &str, inputs: Vec<ModuleInput>, mut handler: F) -> &mut Self
+    where
+        O: Message,
+        F: FnMut(&ModuleInputs<B>) -> Option<O> + 'static,
+    {
+        let handler: MapHandler<B> = Box::new(move |inputs| {
+            handler(inputs).map(|output| proto::encode(&output).expect("error when encoding map output"))
+        });
+        self.add_module(name, inputs, ModuleKind::Map(handler))
+    }
+
+    /// Adds a store module writing into a fresh `S`, any of the single threaded mock stores.
+    pub fn store<S, F>(&mut self, name: &str, inputs: Vec<ModuleInput>, mut handler: F) -> &mut Self
+    where
+        S: HasHandle<Handle = Local>,
+        F: FnMut(&ModuleInputs<B>, &S) + 'static,
+    {
+        let handler: StoreHandler<B> = Box::new(move |inputs, handle| {
+            handler(inputs, &S::from_handle(handle.clone()))
+        });
+        self.add_module(name, inputs, ModuleKind::Store { handler, handle: Local::default() })
+    }
+
+    /// Block number given to the first block, the next ones follow from it. Defaults to 0.
+    pub fn starting_at(&mut self, start_block: u64) -> &mut Self {
+        self.start_block = start_block;
+        self
+    }
+
+    /// Runs every module over `blocks`, outputs accumulate across calls.
+    pub fn run(&mut self, blocks: &[B]) {
+        let order = self.execution_order();
+
+        for (block_num, block) in (self.start_block..).zip(blocks) {
+            for &module_idx in &order {
+                let inputs = ModuleInputs {
+                    block_num,
+                    source: block,
+                    inputs: self.modules[module_idx]
+                        .inputs
+                        .iter()
+                        .map(|input| self.resolve(input, block_num))
+                        .collect(),
+                };
+
+                let map_output = match &mut self.modules[module_idx].kind {
+                    ModuleKind::Map(handler) => handler(&inputs),
+                    ModuleKind::Store { handler, handle } => {
+                        handle.begin_block(block_num);
+                        handler(&inputs, handle);
+                        None
+                    }
+                };
+
+                if let Some(output) = map_output {
+                    let name = self.modules[module_idx].name.clone();
+                    self.map_outputs.entry(name).or_default().insert(block_num, output);
+                }
+            }
+        }
+
+        self.start_block += blocks.len() as u64;
+    }
+
+    /// Decoded output of map module `name` at `block`.
+    pub fn map_output<O: Message + Default>(&self, name: &str, block: u64) -> Option<O> {
+        self.map_outputs
+            .get(name)
+            .and_then(|outputs| outputs.get(&block))
+            .map(|bytes| proto::decode(bytes).unwrap_or_else(|e| panic!("cannot decode output of {}: {}", name, e)))
+    }
+
+    /// The store of store module `name`, sharing its data.
+    pub fn store_of<S: HasHandle<Handle = Local>>(&self, name: &str) -> S {
+        S::from_handle(self.store_handle(name).clone())
+    }
+
+    /// Deltas store module `name` emitted during `block`.
+    pub fn store_deltas(&self, name: &str, block: u64) -> Vec<StoreDelta> {
+        self.store_handle(name).deltas_at(block)
+    }
+
+    fn add_module(&mut self, name: &str, inputs: Vec<ModuleInput>, kind: ModuleKind<B>) -> &mut Self {
+        if self.modules.iter().any(|m| m.name == name) {
+            panic!("module {} is declared twice", name);
+        }
+
+        self.modules.push(Module { name: name.to_string(), inputs, kind });
+        self
+    }
+
+    fn module(&self, name: &str) -> &Module<B> {
+        self.modules
+            .iter()
+            .find(|m| m.name == name)
+            .unwrap_or_else(|| panic!("unknown module {}", name))
+    }
+
+    fn store_handle(&self, name: &str) -> &Local {
+        match &self.module(name).kind {
+            ModuleKind::Store { handle, .. } => handle,
+            ModuleKind::Map(_) => panic!("module {} is a map, not a store", name),
+        }
+    }
+
+    fn resolve(&self, input: &ModuleInput, block_num: u64) -> ResolvedInput {
+        match input {
+            ModuleInput::Source => ResolvedInput::Source,
+            ModuleInput::Map(name) => {
+                if !matches!(self.module(name).kind, ModuleKind::Map(_)) {
+                    panic!("input {} is declared as a map but is a store", name);
+                }
+
+                ResolvedInput::Map(
+                    self.map_outputs
+                        .get(name)
+                        .and_then(|outputs| outputs.get(&block_num))
+                        .cloned(),
+                )
+            }
+            ModuleInput::Store { module_name, mode } => ResolvedInput::Store {
+                handle: self.store_handle(module_name).clone(),
+                mode: *mode,
+            },
+        }
+    }
+
+    /// Module indices in topological order, panics on unknown inputs and cycles.
+    fn execution_order(&self) -> Vec<usize> {
+        // 0 = not visited, 1 = in progress, 2 = done
+        let mut marks = vec![0u8; self.modules.len()];
+        let mut order = Vec::with_capacity(self.modules.len());
+
+        fn visit<B>(modules: &[Module<B>], idx: usize, marks: &mut [u8], order: &mut Vec<usize>) {
+            match marks[idx] {
+                2 => return,
+                1 => panic!("module {} depends on itself", modules[idx].name),
+                _ => {}
+            }
+
+            marks[idx] = 1;
+            for dependency in modules[idx].inputs.iter().filter_map(ModuleInput::module_name) {
+                let dep_idx = modules
+                    .iter()
+                    .position(|m| m.name == dependency)
+                    .unwrap_or_else(|| panic!("module {} has unknown input {}", modules[idx].name, dependency));
+                visit(modules, dep_idx, marks, order);
+            }
+            marks[idx] = 2;
+            order.push(idx);
+        }
+
+        for idx in 0..self.modules.len() {
+            visit(&self.modules, idx, &mut marks, &mut order);
+        }
+
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::{prelude::*, store::DeltaInt64};
+    use super::*;
+    use crate::mock_store::store::MockStore;
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    struct Block {
+        #[prost(int64, tag = "1")]
+        amount: i64,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    struct Total {
+        #[prost(int64, tag = "1")]
+        value: i64,
+    }
+
+    #[test]
+    fn modules_run_in_dependency_order() {
+        let mut executor = ModuleExecutor::<Block>::new();
+        // declared before the modules it depends on
+        executor
+            .map("map_total", vec![ModuleInput::store_get("store_total")], |inputs| {
+                let total: Option<i64> = inputs.store::<MockStore>(0).get_last("total");
+                Some(Total { value: total.unwrap_or_default() })
+            })
+            .map("map_changes", vec![ModuleInput::store_deltas("store_total")], |inputs| {
+                let deltas = inputs.deltas::<DeltaInt64>(0);
+                Some(Total { value: deltas.deltas.iter().map(|d| d.new_value - d.old_value).sum() })
+            })
+            .store("store_total", vec![ModuleInput::map("map_amount")], |inputs, store: &MockStore| {
+                store.add(1, "total", inputs.map::<Block>(0).unwrap().amount);
+            })
+            .map("map_amount", vec![ModuleInput::Source], |inputs| Some(inputs.source().clone()));
+
+        executor.starting_at(10).run(&[Block { amount: 3 }, Block { amount: 4 }]);
+
+        assert_eq!(executor.map_output::<Total>("map_total", 11), Some(Total { value: 7 }));
+        assert_eq!(executor.map_output::<Total>("map_changes", 11), Some(Total { value: 4 }));
+        let total: Option<i64> = executor.store_of::<MockStore>("store_total").get_last("total");
+        assert_eq!(total, Some(7));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a store input in get mode")]
+    fn deltas_inputs_cannot_be_read_as_stores() {
+        let mut executor = ModuleExecutor::<Block>::new();
+        executor
+            .store("store_total", vec![], |_, _: &MockStore| {})
+            .map("map_total", vec![ModuleInput::store_deltas("store_total")], |inputs| {
+                inputs.store::<MockStore>(0);
+                None::<Total>
+            });
+        executor.run(&[Block { amount: 1 }]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Exports store contents and recorded deltas to JSON Lines and CSV.
+//!
+//! Meant for looking at test outputs with jq or a spreadsheet. Values are
+//! decoded per store: big numbers stay strings so nothing loses precision,
+//! protos are objects keyed by their field names (through serde, so the message
+//! type has to derive `Serialize`), bytes are `0x` hex.
+//!
+//! ```no_run
+//! # use std::fs;
+//! # use Stores_and_Deltas::mock_store::{export, handle::StoreHandle, store::{HasHandle, MockStore}};
+//! # fn main() -> std::io::Result<()> {
+//! # let store = MockStore::default();
+//! let deltas = store.handle().recorded_deltas();
+//! fs::write("volume.jsonl", export::state_jsonl(&store))?;
+//! fs::write("volume_deltas.csv", export::deltas_csv(&store, &deltas))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Delta lines are [`JsonDelta`]s with their block set. [`read_deltas_jsonl`] reads
+//! them back through the same store, proto objects included (their type then also
+//! has to derive `Deserialize`), ready for `Snapshot::apply`.
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use substreams::pb::substreams::StoreDelta;
+use crate::mock_store::{
+    handle::StoreHandle,
+    jsonl::{self, JsonDelta, JsonlError},
+    store::{split_array, BaseMockArrayStore, BaseMockProtoStore, BaseMockStore, HasHandle, StoreConfig},
+    traits::FromBytesProto,
+};
+
+/// How a store turns its values into JSON for exports.
+pub trait ExportValue {
+    fn export_value(&self, bytes: &[u8]) -> Value;
+}
+
+impl<H: StoreHandle> ExportValue for BaseMockStore<H> {
+    fn export_value(&self, bytes: &[u8]) -> Value {
+        jsonl::decode_value(self.value_type().as_ref(), bytes)
+    }
+}
+
+impl<T: FromBytesProto + Serialize, H: StoreHandle> ExportValue for BaseMockProtoStore<T, H> {
+    fn export_value(&self, bytes: &[u8]) -> Value {
+        crate::mock_store::proto::decode::<T>(bytes)
+            .ok()
+            .and_then(|value| serde_json::to_value(value).ok())
+            .unwrap_or_else(|| Value::String(format!("0x{}", hex::encode(bytes))))
+    }
+}
+
+impl<H: StoreHandle> ExportValue for BaseMockArrayStore<H> {
+    fn export_value(&self, bytes: &[u8]) -> Value {
+        // split_array panics on invalid UTF-8
+        if std::str::from_utf8(bytes).is_err() {
+            return Value::String(format!("0x{}", hex::encode(bytes)));
+        }
+
+        let items: Vec<String> = split_array(bytes.to_vec()).unwrap_or_default();
+        items.into_iter().map(Value::from).collect()
+    }
+}
+
+/// The other way around from [`ExportValue`], for reading exports back.
+pub trait ImportValue {
+    fn import_value(&self, value: &Value) -> Result<Vec<u8>, String>;
+}
+
+impl<H: StoreHandle> ImportValue for BaseMockStore<H> {
+    fn import_value(&self, value: &Value) -> Result<Vec<u8>, String> {
+        jsonl::encode_value(self.value_type().as_ref(), value)
+    }
+}
+
+impl<T: FromBytesProto + DeserializeOwned, H: StoreHandle> ImportValue for BaseMockProtoStore<T, H> {
+    fn import_value(&self, value: &Value) -> Result<Vec<u8>, String> {
+        match value {
+            // values that didn't decode were exported as hex
+            Value::String(s) => from_hex(s),
+            value => {
+                let message: T = serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
+                crate::mock_store::proto::encode(&message).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+impl<H: StoreHandle> ImportValue for BaseMockArrayStore<H> {
+    fn import_value(&self, value: &Value) -> Result<Vec<u8>, String> {
+        match value {
+            Value::String(s) => from_hex(s),
+            Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(item) => Ok(format!("{};", item)),
+                    other => Err(format!("expected a string item, got {}", other)),
+                })
+                .collect::<Result<String, String>>()
+                .map(String::into_bytes),
+            other => Err(format!("expected an array, got {}", other)),
+        }
+    }
+}
+
+/// One `{"key", "ordinal", "value"}` line per key with its last value, sorted by key.
+pub fn state_jsonl<S: HasHandle + ExportValue>(store: &S) -> String {
+    last_values(store)
+        .into_iter()
+        .map(|(key, ordinal, bytes)| {
+            json!({ "key": key, "ordinal": ordinal, "value": store.export_value(&bytes) }).to_string() + "\n"
+        })
+        .collect()
+}
+
+/// One [`JsonDelta`] line per delta, blocks in the given order.
+pub fn deltas_jsonl<S: ExportValue>(store: &S, deltas: &[(u64, Vec<StoreDelta>)]) -> String {
+    let mut out = String::new();
+    for (block_num, block_deltas) in deltas {
+        for delta in block_deltas {
+            let mut line = JsonDelta::from_delta_with(delta, |bytes| store.export_value(bytes));
+            line.block = Some(*block_num);
+            out.push_str(&serde_json::to_string(&line).expect("json deltas always serialize"));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Reads the output of [`deltas_jsonl`] back, grouped by block, see [`jsonl::read_block_deltas`].
+pub fn read_deltas_jsonl<S: ImportValue>(
+    store: &S,
+    content: &str,
+    block: u64,
+) -> Result<Vec<(u64, Vec<StoreDelta>)>, JsonlError> {
+    jsonl::read_block_deltas_with(content, block, |value| store.import_value(value))
+}
+
+/// `key,ordinal,value` rows with the last value of every key, sorted by key.
+pub fn state_csv<S: HasHandle + ExportValue>(store: &S) -> String {
+    let mut out = String::from("key,ordinal,value\n");
+    for (key, ordinal, bytes) in last_values(store) {
+        let value = store.export_value(&bytes);
+        push_row(&mut out, &[key, ordinal.to_string(), csv_value(&value)]);
+    }
+    out
+}
+
+/// `block,operation,ordinal,key,old_value,new_value` rows, missing values are empty cells.
+pub fn deltas_csv<S: ExportValue>(store: &S, deltas: &[(u64, Vec<StoreDelta>)]) -> String {
+    let mut out = String::from("block,operation,ordinal,key,old_value,new_value\n");
+    for (block_num, block_deltas) in deltas {
+        for delta in block_deltas {
+            let line = JsonDelta::from_delta_with(delta, |bytes| store.export_value(bytes));
+            push_row(
+                &mut out,
+                &[
+                    block_num.to_string(),
+                    line.operation,
+                    line.ordinal.to_string(),
+                    line.key,
+                    csv_value(&line.old_value),
+                    csv_value(&line.new_value),
+                ],
+            );
+        }
+    }
+    out
+}
+
+// copied out so decoding doesn't happen under the lock
+fn last_values<S: HasHandle>(store: &S) -> Vec<(String, u64, Vec<u8>)> {
+    let state = store.handle().read();
+    let mut values: Vec<_> = state
+        .kv
+        .iter()
+        .filter_map(|(key, versions)| {
+            let (ordinal, bytes) = versions.last()?;
+            Some((key.clone(), *ordinal, bytes.clone()))
+        })
+        .collect();
+    values.sort_by(|a, b| a.0.cmp(&b.0));
+    values
+}
+
+// strings go in as is, anything else (numbers, proto objects, arrays) as its JSON text
+fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+    let digits = s.strip_prefix("0x").ok_or_else(|| format!("expected a 0x hex string, got {:?}", s))?;
+    hex::decode(digits).map_err(|e| format!("invalid hex bytes: {}", e))
+}
+
+// RFC 4180 quoting, only when a cell needs it
+fn push_row(out: &mut String, cells: &[String]) {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell.clone()
+            }
+        })
+        .collect();
+    out.push_str(&cells.join(","));
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{
+        delta::DeltaRecorder,
+        snapshot::Snapshot,
+        store::{MockArrayStore, MockProtoStore},
+    };
+
+    #[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+    struct Pool {
+        #[prost(string, tag = "1")]
+        address: String,
+        #[prost(uint64, tag = "2")]
+        fee: u64,
+    }
+
+    #[test]
+    fn proto_deltas_round_trip_through_apply() {
+        let store = <MockProtoStore<Pool> as StoreNew>::new();
+        store.begin_block(1);
+        store.set(5, "pool:0xab", &Pool { address: "0xab".to_string(), fee: 3 });
+        store.begin_block(2);
+        // a lower ordinal than block 1's, block order has to win
+        store.set(1, "pool:0xab", &Pool { address: "0xab".to_string(), fee: 5 });
+
+        let exported = deltas_jsonl(&store, &store.handle().recorded_deltas());
+        assert!(exported.contains(r#""new_value":{"address":"0xab","fee":5}"#));
+
+        let mut snapshot = Snapshot::default();
+        snapshot.apply(&read_deltas_jsonl(&store, &exported, 0).unwrap()).unwrap();
+        assert_eq!(snapshot.entries, Snapshot::capture(&store).entries);
+        assert_eq!(snapshot.block, 2);
+    }
+
+    #[test]
+    fn array_values_round_trip() {
+        let store = <MockArrayStore as StoreGet<Vec<String>>>::new(0);
+        let bytes = b"a;b;".to_vec();
+        assert_eq!(store.import_value(&store.export_value(&bytes)), Ok(bytes));
+    }
+}
@@ -0,0 +1,100 @@
+//! Content hashes of store state.
+//!
+//! Two stores with the same keys, the same last values and the same deleted
+//! prefixes have the same fingerprint, whatever order things were written or
+//! deleted in and however their maps iterate. Value histories, ordinals and
+//! deltas are left out, compare deltas separately (the determinism check in
+//! `harness` does both).
+//!
+//! ```no_run
+//! # use Stores_and_Deltas::mock_store::{fingerprint::StoreFingerprint, store::MockStore};
+//! # let (store, store_a, store_b) = (MockStore::default(), MockStore::default(), MockStore::default());
+//! assert_eq!(store_a.fingerprint(), store_b.fingerprint());
+//! println!("{}", store.fingerprint()); // sha256 hex
+//! ```
+use std::fmt;
+use sha2::{Digest, Sha256};
+use crate::mock_store::{handle::StoreHandle, store::HasHandle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+pub trait StoreFingerprint {
+    fn fingerprint(&self) -> Fingerprint;
+}
+
+impl<S: HasHandle> StoreFingerprint for S {
+    fn fingerprint(&self) -> Fingerprint {
+        let state = self.handle().read();
+        let mut hasher = Sha256::new();
+
+        let mut keys: Vec<&String> = state.kv.keys().collect();
+        keys.sort();
+
+        // everything length prefixed so ("ab", "c") and ("a", "bc") hash differently
+        for key in keys {
+            let Some((_, value)) = state.kv[key].last() else { continue };
+            hash_bytes(&mut hasher, key.as_bytes());
+            hash_bytes(&mut hasher, value);
+        }
+
+        let mut prefixes: Vec<&String> = state.deleted_prefixes.iter().collect();
+        prefixes.sort();
+        prefixes.dedup();
+        hasher.update((prefixes.len() as u64).to_be_bytes());
+        for prefix in prefixes {
+            hash_bytes(&mut hasher, prefix.as_bytes());
+        }
+
+        Fingerprint(hasher.finalize().into())
+    }
+}
+
+fn hash_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_be_bytes());
+    hasher.update(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::store::MockStore;
+
+    #[test]
+    fn write_order_and_history_are_left_out() {
+        let a = <MockStore as StoreNew>::new();
+        a.set(1, "x", &1i64);
+        a.set(2, "y", &2i64);
+        a.delete_prefix(3, &"old:".to_string());
+
+        let b = <MockStore as StoreNew>::new();
+        b.delete_prefix(1, &"old:".to_string());
+        b.set(2, "y", &5i64);
+        b.set(3, "y", &2i64);
+        b.set(4, "x", &1i64);
+
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.fingerprint().to_string().len(), 64);
+    }
+
+    #[test]
+    fn values_keys_and_prefixes_count() {
+        let store = <MockStore as StoreNew>::new();
+        store.set(1, "ab", &"c".to_string());
+        let before = store.fingerprint();
+
+        let other = <MockStore as StoreNew>::new();
+        other.set(1, "a", &"bc".to_string());
+        assert_ne!(before, other.fingerprint());
+
+        store.delete_prefix(2, &"zz".to_string());
+        assert_ne!(before, store.fingerprint());
+    }
+}
@@ -0,0 +1,229 @@
+//! Seeds mock stores from JSON or TOML fixture files.
+//!
+//! Test setup becomes data instead of code. Values are encoded through the same
+//! `ToBytes`/`ToBytesProto` paths as live writes and written like a `set` would,
+//! so a seeded store can't be told apart from one a handler filled.
+//!
+//! ```json
+//! {
+//!   "value_type": "bigint",
+//!   "entries": [
+//!     { "key": "volume", "ordinal": 1, "value": "1200" },
+//!     { "key": "pair:0xab", "value": 3 }
+//!   ]
+//! }
+//! ```
+//!
+//! or the same in TOML with `[[entries]]` tables. Protos are written as JSON
+//! objects using their field names and decoded through serde into the store's type.
+use std::{fmt, fs, io, path::Path};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use crate::mock_store::{
+    handle::StoreHandle,
+    store::{BaseMockProtoStore, HasHandle, StoreConfig},
+    traits::ToBytesProto,
+    value_type::ValueType,
+};
+use substreams::prelude::StoreSet;
+
+#[derive(Debug)]
+pub enum FixtureError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    /// only `.json` and `.toml` files are understood
+    UnknownFormat(String),
+    MissingValueType,
+    InvalidValue { key: String, reason: String },
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixtureError::Io(e) => write!(f, "cannot read fixture: {}", e),
+            FixtureError::Json(e) => write!(f, "invalid JSON fixture: {}", e),
+            FixtureError::Toml(e) => write!(f, "invalid TOML fixture: {}", e),
+            FixtureError::UnknownFormat(path) => write!(f, "fixture {} is neither .json nor .toml", path),
+            FixtureError::MissingValueType => write!(f, "fixture has no value_type and the store has none configured"),
+            FixtureError::InvalidValue { key, reason } => write!(f, "invalid value for key {}: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for FixtureError {}
+
+impl From<io::Error> for FixtureError {
+    fn from(e: io::Error) -> Self {
+        FixtureError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for FixtureError {
+    fn from(e: serde_json::Error) -> Self {
+        FixtureError::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for FixtureError {
+    fn from(e: toml::de::Error) -> Self {
+        FixtureError::Toml(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FixtureEntry {
+    pub key: String,
+    #[serde(default)]
+    pub ordinal: u64,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Fixture {
+    /// falls back to the value type configured on the store when absent
+    #[serde(default)]
+    pub value_type: Option<ValueType>,
+    #[serde(default)]
+    pub entries: Vec<FixtureEntry>,
+}
+
+impl Fixture {
+    /// Reads a fixture, the format is picked from the file extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, FixtureError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&content),
+            Some("toml") => Self::from_toml(&content),
+            _ => Err(FixtureError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, FixtureError> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, FixtureError> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Writes every entry into `store`, in file order, like `set` would.
+    ///
+    /// Works for any store whose values have a generic encoding, proto stores go
+    /// through [`Fixture::seed_proto`].
+    pub fn seed<S: HasHandle>(&self, store: &S) -> Result<(), FixtureError> {
+        let value_type = self
+            .value_type
+            .clone()
+            .or_else(|| store.value_type())
+            .ok_or(FixtureError::MissingValueType)?;
+
+        // encode everything first so a bad entry doesn't leave the store half seeded
+        let encoded = self
+            .entries
+            .iter()
+            .map(|entry| {
+                value_type
+                    .encode_json(&entry.value)
+                    .map(|bytes| (entry, bytes))
+                    .map_err(|reason| FixtureError::InvalidValue { key: entry.key.clone(), reason })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (entry, bytes) in encoded {
+            store.handle().push_bytes(entry.ordinal, &entry.key, bytes);
+        }
+
+        if store.value_type().is_none() {
+            store.configure(store.update_policy(), Some(value_type));
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`Fixture::seed`] for proto stores, values are JSON objects decoded with serde.
+    pub fn seed_proto<T, H>(&self, store: &BaseMockProtoStore<T, H>) -> Result<(), FixtureError>
+    where
+        T: ToBytesProto + DeserializeOwned,
+        H: StoreHandle,
+    {
+        let values = self
+            .entries
+            .iter()
+            .map(|entry| {
+                serde_json::from_value::<T>(entry.value.clone())
+                    .map(|value| (entry, value))
+                    .map_err(|e| FixtureError::InvalidValue { key: entry.key.clone(), reason: e.to_string() })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (entry, value) in values {
+            store.set(entry.ordinal, &entry.key, &value);
+        }
+
+        if store.value_type().is_none() {
+            store.configure(store.update_policy(), self.value_type.clone());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::store::{MockProtoStore, MockStore};
+
+    #[derive(Clone, PartialEq, ::prost::Message, Deserialize)]
+    struct Pool {
+        #[prost(string, tag = "1")]
+        address: String,
+        #[prost(uint64, tag = "2")]
+        fee: u64,
+    }
+
+    #[test]
+    fn json_and_toml_fixtures_seed_the_same_store() {
+        let json = Fixture::from_json(
+            r#"{ "value_type": "bigint", "entries": [{ "key": "volume", "ordinal": 1, "value": "1200" }, { "key": "count", "value": 3 }] }"#,
+        )
+        .unwrap();
+        let toml = Fixture::from_toml(
+            "value_type = \"bigint\"\n[[entries]]\nkey = \"volume\"\nordinal = 1\nvalue = \"1200\"\n[[entries]]\nkey = \"count\"\nvalue = 3\n",
+        )
+        .unwrap();
+        assert_eq!(json, toml);
+
+        let store = <MockStore as StoreNew>::new();
+        json.seed(&store).unwrap();
+        assert_eq!(<MockStore as StoreGet<BigInt>>::get_at(&store, 1, "volume"), Some(BigInt::from(1200)));
+        assert_eq!(<MockStore as StoreGet<BigInt>>::get_last(&store, "count"), Some(BigInt::from(3)));
+        assert_eq!(store.value_type(), Some(ValueType::BigInt));
+    }
+
+    #[test]
+    fn bad_entries_leave_the_store_untouched() {
+        let fixture = Fixture::from_json(
+            r#"{ "value_type": "int64", "entries": [{ "key": "a", "value": 1 }, { "key": "b", "value": "x" }] }"#,
+        )
+        .unwrap();
+        let store = <MockStore as StoreNew>::new();
+
+        assert!(matches!(fixture.seed(&store), Err(FixtureError::InvalidValue { ref key, .. }) if key == "b"));
+        assert!(!store.handle().contains_key("a"));
+        let untyped = Fixture { value_type: None, entries: Vec::new() };
+        assert!(matches!(untyped.seed(&store), Err(FixtureError::MissingValueType)));
+    }
+
+    #[test]
+    fn proto_fixtures_go_through_serde() {
+        let fixture = Fixture::from_json(r#"{ "entries": [{ "key": "pool", "value": { "address": "0xab", "fee": 3 } }] }"#).unwrap();
+        let store = <MockProtoStore<Pool> as StoreNew>::new();
+        fixture.seed_proto(&store).unwrap();
+        assert_eq!(store.get_last("pool"), Some(Pool { address: "0xab".to_string(), fee: 3 }));
+    }
+}
@@ -0,0 +1,270 @@
+//! Copy-on-write forks of the mock stores.
+//!
+//! A fork starts out sharing the parent's key/value map, a persistent map where
+//! writes on either side only copy the parts they touch, so forking a big store
+//! per "what if" branch or per simulated reorg stays cheap. The fork records its
+//! own deltas, which is what [`Fork::changes`] reports and what [`Fork::commit`]
+//! replays onto the parent, once it checked the parent didn't move under them.
+//!
+//! ```no_run
+//! # use substreams::{prelude::*, scalar::BigInt};
+//! # use Stores_and_Deltas::mock_store::{fork::StoreFork, store::MockStore};
+//! # let store = <MockStore as StoreNew>::new();
+//! let branch = store.fork();
+//! branch.add(1, "pool:0xab:volume", BigInt::from(10));
+//! assert_eq!(branch.changes().len(), 1);
+//! branch.commit().unwrap(); // or branch.discard(), the parent is untouched until then
+//! ```
+use std::{collections::HashMap, fmt, ops::Deref};
+use substreams::pb::substreams::{store_delta::Operation, StoreDelta};
+use crate::mock_store::{delta_ops::StoreDeltasExt, handle::StoreHandle, store::HasHandle};
+
+/// A fork write whose old value isn't what the parent has anymore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkConflict {
+    pub block: u64,
+    pub key: String,
+    /// old value of the fork's delta, None for a create
+    pub expected: Option<Vec<u8>>,
+    /// last value of the key in the parent at that point
+    pub found: Option<Vec<u8>>,
+}
+
+impl fmt::Display for ForkConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<Vec<u8>>| match value {
+            Some(bytes) => format!("0x{}", hex::encode(bytes)),
+            None => "nothing".to_string(),
+        };
+        write!(
+            f,
+            "key {:?} changed on the parent since the fork: block {} expects {}, found {}",
+            self.key,
+            self.block,
+            show(&self.expected),
+            show(&self.found)
+        )
+    }
+}
+
+impl std::error::Error for ForkConflict {}
+
+/// A copy-on-write child of a store, derefs to a store of the same type.
+#[derive(Debug)]
+pub struct Fork<S> {
+    child: S,
+    parent: S,
+    /// how many deleted prefixes the parent had when forked, the rest are the child's
+    prefixes_at_fork: usize,
+}
+
+pub trait StoreFork: Sized {
+    /// A child sharing this store's data, see the module docs.
+    fn fork(&self) -> Fork<Self>;
+}
+
+impl<S: HasHandle> StoreFork for S {
+    fn fork(&self) -> Fork<Self> {
+        let parent = S::from_handle(self.handle().clone());
+        let child = S::from_handle(Default::default());
+
+        let prefixes_at_fork = {
+            let parent_state = parent.handle().read();
+            *child.handle().write() = parent_state.without_deltas();
+            parent_state.deleted_prefixes.len()
+        };
+
+        Fork { child, parent, prefixes_at_fork }
+    }
+}
+
+impl<S> Deref for Fork<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.child
+    }
+}
+
+impl<S: HasHandle> Fork<S> {
+    /// Net change of every key the fork wrote, see [`StoreDeltasExt::collapse`].
+    ///
+    /// Only the fork's own writes, the old values are what the parent had at the
+    /// fork. Writes made to the parent since then don't show up here.
+    pub fn changes(&self) -> Vec<StoreDelta> {
+        let deltas: Vec<StoreDelta> = self
+            .child
+            .handle()
+            .recorded_deltas()
+            .into_iter()
+            .flat_map(|(_, deltas)| deltas)
+            .collect();
+        deltas.collapse()
+    }
+
+    /// Prefixes deleted since the fork, in call order.
+    pub fn deleted_prefixes(&self) -> Vec<String> {
+        self.child.handle().read().deleted_prefixes[self.prefixes_at_fork..].to_vec()
+    }
+
+    /// Drops the fork, the parent never sees its writes.
+    pub fn discard(self) {}
+
+    /// Replays the fork's writes onto the parent, block by block and in write
+    /// order, so the parent records the same deltas the fork did. The parent ends
+    /// up at the fork's current block.
+    ///
+    /// Every old value is checked against the parent first: when the parent
+    /// changed a key the fork wrote since the fork, nothing is replayed and the
+    /// first such write is returned. The usual limit and ordinal checks apply.
+    pub fn commit(self) -> Result<S, ForkConflict> {
+        self.check_parent()?;
+        let handle = self.parent.handle();
+
+        for (block, deltas) in self.child.handle().recorded_deltas() {
+            handle.begin_block(block);
+            for delta in deltas {
+                if delta.operation == Operation::Delete as i32 {
+                    handle.delete_key(delta.ordinal, &delta.key);
+                } else {
+                    handle.push_bytes(delta.ordinal, &delta.key, delta.new_value);
+                }
+            }
+        }
+        handle.begin_block(self.child.handle().current_block());
+
+        let prefixes = self.deleted_prefixes();
+        handle.write().deleted_prefixes.extend(prefixes);
+
+        Ok(self.parent)
+    }
+
+    // walks the fork's deltas over the parent's current values, without writing
+    fn check_parent(&self) -> Result<(), ForkConflict> {
+        let parent = self.parent.handle();
+        // key -> its value as the replay would leave it so far, None once deleted
+        let mut current: HashMap<String, Option<Vec<u8>>> = HashMap::new();
+
+        for (block, deltas) in self.child.handle().recorded_deltas() {
+            for delta in deltas {
+                let found = current
+                    .entry(delta.key.clone())
+                    .or_insert_with(|| parent.get_bytes_last(&delta.key));
+                let expected = match delta.operation == Operation::Create as i32 {
+                    true => None,
+                    false => Some(delta.old_value),
+                };
+
+                if *found != expected {
+                    return Err(ForkConflict { block, key: delta.key, expected, found: found.clone() });
+                }
+                *found = match delta.operation == Operation::Delete as i32 {
+                    true => None,
+                    false => Some(delta.new_value),
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::{prelude::*, scalar::BigInt};
+    use super::*;
+    use crate::mock_store::{delta::DeltaRecorder, store::{MockStore, StoreScan}};
+
+    fn parent() -> MockStore {
+        let store = <MockStore as StoreNew>::new();
+        store.begin_block(1);
+        store.add(1, "volume", BigInt::from(1));
+        store.add(2, "stale:0xab", BigInt::from(1));
+        store
+    }
+
+    #[test]
+    fn commit_replays_block_by_block() {
+        let store = parent();
+        let branch = store.fork();
+        branch.begin_block(2);
+        branch.add(1, "volume", BigInt::from(10));
+        branch.begin_block(3);
+        branch.add(1, "volume", BigInt::from(5));
+        assert_eq!(store.get_last("volume"), Some(BigInt::from(1)));
+
+        let store = branch.commit().unwrap();
+        assert_eq!(store.get_last("volume"), Some(BigInt::from(16)));
+        assert_eq!(store.store_deltas(2).len(), 1);
+        assert_eq!(store.store_deltas(3)[0].old_value, b"11".to_vec());
+        assert_eq!(store.handle().current_block(), 3);
+    }
+
+    #[test]
+    fn changes_collapse_the_fork_writes() {
+        let branch = parent().fork();
+        branch.add(3, "volume", BigInt::from(10));
+        branch.add(4, "volume", BigInt::from(5));
+        branch.add(5, "fees", BigInt::from(2));
+
+        let changes = branch.changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].old_value.as_slice(), changes[0].new_value.as_slice()), (&b"1"[..], &b"16"[..]));
+        assert_eq!(changes[1].operation, Operation::Create as i32);
+    }
+
+    #[test]
+    fn commit_fails_when_the_parent_moved() {
+        let store = parent();
+        let branch = store.fork();
+        branch.add(3, "volume", BigInt::from(10));
+        branch.add(4, "fees", BigInt::from(2));
+        store.add(5, "volume", BigInt::from(100));
+
+        assert_eq!(
+            branch.commit().unwrap_err(),
+            ForkConflict { block: 1, key: "volume".to_string(), expected: Some(b"1".to_vec()), found: Some(b"101".to_vec()) }
+        );
+        // nothing was replayed
+        assert_eq!(store.get_last("volume"), Some(BigInt::from(101)));
+        assert!(store.keys_with_prefix("fees").is_empty());
+    }
+
+    #[test]
+    fn commit_goes_through_parent_writes_to_other_keys() {
+        let store = parent();
+        let branch = store.fork();
+        branch.add(3, "volume", BigInt::from(10));
+        store.add(4, "fees", BigInt::from(2));
+
+        let store = branch.commit().unwrap();
+        assert_eq!(store.get_last("volume"), Some(BigInt::from(11)));
+        assert_eq!(store.get_last("fees"), Some(BigInt::from(2)));
+    }
+
+    #[test]
+    fn discard_leaves_the_parent_untouched() {
+        let store = parent();
+        let branch = store.fork();
+        branch.add(3, "volume", BigInt::from(10));
+        branch.delete_prefix(4, &"stale:".to_string());
+        assert_eq!(branch.deleted_prefixes(), vec!["stale:"]);
+        branch.discard();
+
+        assert_eq!(store.get_last("volume"), Some(BigInt::from(1)));
+        assert_eq!(store.keys_with_prefix("stale:"), vec!["stale:0xab"]);
+        assert_eq!(store.store_deltas(1).len(), 2);
+    }
+
+    #[test]
+    fn commit_carries_deleted_prefixes() {
+        let store = parent();
+        store.delete_prefix(3, &"gone:".to_string());
+        let branch = store.fork();
+        branch.delete_prefix(4, &"stale:".to_string());
+
+        assert_eq!(branch.deleted_prefixes(), vec!["stale:"]);
+        let store = branch.commit().unwrap();
+        assert!(store.keys_with_prefix("stale:").is_empty());
+        assert_eq!(store.handle().deleted_prefixes(), vec!["gone:", "stale:"]);
+    }
+}
@@ -0,0 +1,135 @@
+//! Human readable rendering of the bytes a store holds.
+//!
+//! Stores only keep bytes, to show them in golden files, dumps or the CLI they
+//! have to be decoded according to the store's value type: the configured
+//! [`ValueType`] for `MockStore`, the message type for `MockProtoStore<T>`.
+//!
+//! Protos are printed with their `Debug` impl here, prost messages carry no
+//! descriptor to walk their fields with. Given the package's descriptors, dumps
+//! and the CLI render them in protobuf text format instead, see `text_format`.
+use std::fmt::Debug;
+use serde_json::Value;
+use crate::mock_store::{
+    handle::StoreHandle,
+    store::{split_array, BaseMockArrayStore, BaseMockProtoStore, BaseMockStore, StoreConfig},
+    traits::FromBytesProto,
+    value_type::ValueType,
+};
+
+impl ValueType {
+    /// Short name shown in front of values, `BigInt 1200`.
+    pub fn label(&self) -> String {
+        match self {
+            ValueType::BigInt => "BigInt".to_string(),
+            ValueType::BigDecimal => "BigDecimal".to_string(),
+            ValueType::Int64 => "Int64".to_string(),
+            ValueType::Float64 => "Float64".to_string(),
+            ValueType::String => "String".to_string(),
+            ValueType::Bytes => "Bytes".to_string(),
+            ValueType::Proto(message) => message.rsplit('.').next().unwrap_or(message).to_string(),
+        }
+    }
+
+    /// Decodes `bytes` for display, never panics: bytes that don't decode are shown as hex.
+    ///
+    /// Same decoding as [`ValueType::decode_json`], only strings are quoted.
+    pub fn format_bytes(&self, bytes: &[u8]) -> String {
+        if let ValueType::Bytes | ValueType::Proto(_) = self {
+            return format!("0x{}", hex::encode(bytes));
+        }
+
+        match (self, self.try_decode_json(bytes)) {
+            (ValueType::String, Some(Value::String(s))) => format!("{:?}", s),
+            // big numbers are JSON strings, shown without quotes
+            (_, Some(Value::String(s))) => s,
+            (_, Some(value)) => value.to_string(),
+            (_, None) => format!("<invalid 0x{}>", hex::encode(bytes)),
+        }
+    }
+}
+
+/// How a store renders its values, see the module docs.
+pub trait FormatValue {
+    fn value_label(&self) -> String;
+
+    fn format_value(&self, bytes: &[u8]) -> String;
+}
+
+impl<H: StoreHandle> FormatValue for BaseMockStore<H> {
+    fn value_label(&self) -> String {
+        self.value_type().map(|vt| vt.label()).unwrap_or_else(|| "Raw".to_string())
+    }
+
+    // without a value type, text is the most likely thing in there
+    fn format_value(&self, bytes: &[u8]) -> String {
+        match self.value_type() {
+            Some(value_type) => value_type.format_bytes(bytes),
+            None => match std::str::from_utf8(bytes) {
+                Ok(s) => format!("{:?}", s),
+                Err(_) => format!("0x{}", hex::encode(bytes)),
+            },
+        }
+    }
+}
+
+impl<T: FromBytesProto + Debug, H: StoreHandle> FormatValue for BaseMockProtoStore<T, H> {
+    fn value_label(&self) -> String {
+        let type_name = std::any::type_name::<T>();
+        type_name.rsplit("::").next().unwrap_or(type_name).to_string()
+    }
+
+    fn format_value(&self, bytes: &[u8]) -> String {
+        match crate::mock_store::proto::decode::<T>(bytes) {
+            Ok(value) => format!("{:?}", value),
+            Err(_) => format!("<invalid 0x{}>", hex::encode(bytes)),
+        }
+    }
+}
+
+impl<H: StoreHandle> FormatValue for BaseMockArrayStore<H> {
+    fn value_label(&self) -> String {
+        "Array".to_string()
+    }
+
+    fn format_value(&self, bytes: &[u8]) -> String {
+        // split_array panics on invalid UTF-8, this mustn't
+        if std::str::from_utf8(bytes).is_err() {
+            return format!("<invalid 0x{}>", hex::encode(bytes));
+        }
+
+        let items: Vec<String> = split_array(bytes.to_vec()).unwrap_or_default();
+        let items: Vec<String> = items.iter().map(|item| format!("{:?}", item)).collect();
+        format!("[{}]", items.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_store::store::MockArrayStore;
+    use substreams::prelude::*;
+
+    #[test]
+    fn values_decode_per_value_type() {
+        assert_eq!(ValueType::BigInt.format_bytes(b"1200"), "1200");
+        assert_eq!(ValueType::BigDecimal.format_bytes(b"12.5"), "12.5");
+        assert_eq!(ValueType::Int64.format_bytes(b"-3"), "-3");
+        assert_eq!(ValueType::Float64.format_bytes(b"1.5"), "1.5");
+        assert_eq!(ValueType::String.format_bytes(b"0xab"), "\"0xab\"");
+        assert_eq!(ValueType::Bytes.format_bytes(&[0xab, 0x01]), "0xab01");
+    }
+
+    #[test]
+    fn undecodable_values_are_shown_as_hex() {
+        assert_eq!(ValueType::BigInt.format_bytes(b"abc"), "<invalid 0x616263>");
+        assert_eq!(ValueType::String.format_bytes(&[0xff]), "<invalid 0xff>");
+    }
+
+    #[test]
+    fn arrays_list_their_items() {
+        let store = <MockArrayStore as StoreGet<Vec<String>>>::new(0);
+        assert_eq!(store.format_value(b"a;b;"), "[\"a\", \"b\"]");
+        assert_eq!(store.format_value(b""), "[]");
+        assert_eq!(store.format_value(&[0xff, b';']), "<invalid 0xff3b>");
+    }
+}
@@ -0,0 +1,171 @@
+//! Golden-file snapshots of the deltas a run produced.
+//!
+//! The first time a snapshot is asserted the rendered deltas are written to the
+//! file, afterwards the run is compared against it and the test fails with a
+//! line diff. Set `UPDATE_SNAPSHOTS=1` to rewrite the files after an intended
+//! behaviour change. Values are decoded with the store's value type so the files
+//! read like
+//!
+//! ```text
+//! # block 12
+//! Create #1 "volume" BigInt 1200
+//! Update #2 "volume" BigInt 1200 -> 1350
+//! ```
+use std::{fs, path::Path};
+use substreams::pb::substreams::{store_delta::Operation, StoreDelta};
+use crate::mock_store::{format::FormatValue, harness::HarnessRun};
+
+/// Env var that makes snapshot assertions rewrite the files instead of comparing.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "UPDATE_SNAPSHOTS";
+
+/// Renders per-block deltas, one line per delta, in the golden file format.
+pub fn render_deltas<S: FormatValue>(store: &S, deltas: &[(u64, Vec<StoreDelta>)]) -> String {
+    let label = store.value_label();
+    let mut out = String::new();
+
+    for (block_num, block_deltas) in deltas {
+        out.push_str(&format!("# block {}\n", block_num));
+
+        for delta in block_deltas {
+            let operation = Operation::try_from(delta.operation).unwrap_or(Operation::Unset);
+            let values = match operation {
+                Operation::Create => store.format_value(&delta.new_value),
+                Operation::Delete => store.format_value(&delta.old_value),
+                _ => format!(
+                    "{} -> {}",
+                    store.format_value(&delta.old_value),
+                    store.format_value(&delta.new_value)
+                ),
+            };
+            out.push_str(&format!(
+                "{:?} #{} {:?} {} {}\n",
+                operation, delta.ordinal, delta.key, label, values
+            ));
+        }
+    }
+
+    out
+}
+
+/// Compares the rendered deltas against the golden file at `path`, writing it when it
+/// doesn't exist yet or when `UPDATE_SNAPSHOTS` is set.
+#[track_caller]
+pub fn assert_deltas_snapshot<S: FormatValue, P: AsRef<Path>>(
+    path: P,
+    store: &S,
+    deltas: &[(u64, Vec<StoreDelta>)],
+) {
+    let path = path.as_ref();
+    let actual = render_deltas(store, deltas);
+    let update = std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|v| !v.is_empty() && v != "0");
+
+    if update || !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .unwrap_or_else(|e| panic!("cannot create snapshot dir {}: {}", parent.display(), e));
+        }
+        fs::write(path, &actual).unwrap_or_else(|e| panic!("cannot write snapshot {}: {}", path.display(), e));
+        return;
+    }
+
+    let expected = fs::read_to_string(path).unwrap_or_else(|e| panic!("cannot read snapshot {}: {}", path.display(), e));
+    if expected != actual {
+        panic!(
+            "deltas differ from snapshot {} (rerun with {}=1 to accept them):\n{}",
+            path.display(),
+            UPDATE_SNAPSHOTS_ENV,
+            line_diff(&expected, &actual)
+        );
+    }
+}
+
+impl<S: FormatValue> HarnessRun<S> {
+    /// [`assert_deltas_snapshot`] over every block of the run.
+    #[track_caller]
+    pub fn assert_snapshot<P: AsRef<Path>>(&self, path: P) {
+        assert_deltas_snapshot(path, &self.store, &self.deltas);
+    }
+}
+
+// plain LCS diff, golden files are small enough for the quadratic table
+fn line_diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            out.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{delta::DeltaRecorder, store::{MockStore, StoreConfig}, value_type::ValueType};
+
+    fn store() -> MockStore {
+        let store = <MockStore as StoreNew>::new();
+        store.configure(None, Some(ValueType::BigInt));
+        store.begin_block(12);
+        store.add(1, "volume", BigInt::from(1200));
+        store.add(2, "volume", BigInt::from(150));
+        store
+    }
+
+    #[test]
+    fn deltas_render_with_their_decoded_values() {
+        let store = store();
+        assert_eq!(
+            render_deltas(&store, &store.recorded_deltas()),
+            "# block 12\nCreate #1 \"volume\" BigInt 1200\nUpdate #2 \"volume\" BigInt 1200 -> 1350\n"
+        );
+    }
+
+    #[test]
+    fn line_diff_marks_removed_and_added_lines() {
+        assert_eq!(line_diff("a\nb\nc", "a\nc\nd"), "  a\n- b\n  c\n+ d");
+        assert_eq!(line_diff("", "a"), "+ a");
+    }
+
+    #[test]
+    fn snapshots_are_written_then_compared() {
+        let path = std::env::temp_dir().join(format!("golden-{}", std::process::id())).join("volume.txt");
+        let store = store();
+        let _ = fs::remove_file(&path);
+
+        assert_deltas_snapshot(&path, &store, &store.recorded_deltas());
+        assert_eq!(fs::read_to_string(&path).unwrap(), render_deltas(&store, &store.recorded_deltas()));
+        assert_deltas_snapshot(&path, &store, &store.recorded_deltas());
+
+        store.add(3, "volume", BigInt::from(1));
+        let deltas = store.recorded_deltas();
+        let failed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| assert_deltas_snapshot(&path, &store, &deltas)));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(failed.is_err());
+    }
+}
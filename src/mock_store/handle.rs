@@ -0,0 +1,390 @@
+//! Shared handles to the bytes backing the mock stores.
+//!
+//! Every mock store is just a cheap, clonable handle to some interior-mutable
+//! `StoreState`. The original stores used `Rc<RefCell<..>>` which is fine for
+//! single threaded tests but is neither `Send` nor `Sync`, so the handle is
+//! abstracted here and the stores are generic over it.
+//!
+//! The values live in a persistent map (`im::HashMap`): cloning it shares its
+//! structure instead of copying it, so forks and per block checkpoints are
+//! constant time and only the parts touched by later writes get copied. Each
+//! key's history sits behind an `Arc`, so copying a part of the map only bumps
+//! the counts of the histories in it, a write copies nothing but its own key's.
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    ops::{Deref, DerefMut},
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    sync::{Arc, RwLock},
+};
+use substreams::pb::substreams::{
+    module::kind_store::UpdatePolicy,
+    store_delta::Operation,
+    StoreDelta,
+};
+use crate::mock_store::{
+    limits::StoreLimits,
+    ordinal::{LastWrite, OrdinalCheck, OrdinalViolation},
+    value_type::ValueType,
+};
+
+/// every (ordinal, value) written for a key, oldest first
+pub type History = Arc<Vec<(u64, Vec<u8>)>>;
+
+/// key -> its history
+pub type BytesMockStore = im::HashMap<String, History>;
+
+/// Everything a store handle points to: the values and the deltas every write produced.
+#[derive(Debug, Clone, Default)]
+pub struct StoreState {
+    /// persistent map, cloning it is O(1), see the `fork` and `checkpoint` modules
+    pub(crate) kv: BytesMockStore,
+    /// block the next writes belong to
+    pub(crate) block: u64,
+    /// block number -> deltas emitted during that block, in write order
+    pub(crate) deltas: BTreeMap<u64, Vec<StoreDelta>>,
+    /// what the manifest declared for this store, if it came from one
+    pub(crate) update_policy: Option<UpdatePolicy>,
+    pub(crate) value_type: Option<ValueType>,
+    /// every prefix passed to `delete_prefix`, in call order, like the Go store keeps them
+    pub(crate) deleted_prefixes: Vec<String>,
+    pub(crate) limits: StoreLimits,
+    /// key and last value lengths over every key, what `limits.max_total_size` is checked against
+    pub(crate) total_size: u64,
+    pub(crate) ordinal_check: OrdinalCheck,
+    pub(crate) last_write: Option<LastWrite>,
+    /// what a lenient ordinal check let through, in write order
+    pub(crate) ordinal_violations: Vec<OrdinalViolation>,
+}
+
+impl StoreState {
+    /// Removes `key` and records its `Delete` delta, no-op when the key isn't there.
+    fn remove_key(&mut self, ord: u64, key: &str) {
+        let Some(versions) = self.kv.remove(key) else { return };
+        let old_value = versions.last().map(|(_, bytes)| bytes.clone()).unwrap_or_default();
+        self.total_size -= (key.len() + old_value.len()) as u64;
+
+        self.deltas.entry(self.block).or_default().push(StoreDelta {
+            operation: Operation::Delete as i32,
+            ordinal: ord,
+            key: key.to_string(),
+            old_value,
+            new_value: Vec::new(),
+        });
+    }
+
+    /// Records lenient violations, strict ones are returned for the caller to
+    /// panic with once its guard is released.
+    fn check_ordinal(&mut self, key: &str, ord: u64) -> Result<(), OrdinalViolation> {
+        match self.ordinal_check.record(&mut self.last_write, self.block, key, ord) {
+            Some(violation) if self.ordinal_check == OrdinalCheck::Strict => Err(violation),
+            Some(violation) => {
+                self.ordinal_violations.push(violation);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Appends a version of `key` and its delta, the error is what the write
+    /// has to panic with once the caller released its guard.
+    fn push(&mut self, ord: u64, key: &str, bytes: Vec<u8>) -> Result<(), String> {
+        let previous = self.kv.get(key).and_then(|entries| entries.last()).map(|(_, old)| old);
+        let previous_size = previous.map(|old| (key.len() + old.len()) as u64).unwrap_or(0);
+        let total_size = self.total_size - previous_size + (key.len() + bytes.len()) as u64;
+        self.limits.check(key, bytes.len(), total_size).map_err(|e| e.to_string())?;
+        self.check_ordinal(key, ord).map_err(|violation| violation.to_string())?;
+        self.total_size = total_size;
+
+        // only copies this key's history, and only when a checkpoint or fork still shares it
+        let entries = Arc::make_mut(self.kv.entry(key.to_string()).or_default());
+        let (operation, old_value) = match entries.last() {
+            Some((_, old_value)) => (Operation::Update, old_value.clone()),
+            None => (Operation::Create, Vec::new()),
+        };
+        entries.push((ord, bytes.clone()));
+
+        self.deltas.entry(self.block).or_default().push(StoreDelta {
+            operation: operation as i32,
+            ordinal: ord,
+            key: key.to_string(),
+            old_value,
+            new_value: bytes,
+        });
+        Ok(())
+    }
+
+    /// for code filling `kv` directly instead of going through `push_bytes`
+    pub(crate) fn recompute_total_size(&mut self) {
+        self.total_size = self
+            .kv
+            .iter()
+            .filter_map(|(key, versions)| versions.last().map(|(_, bytes)| (key.len() + bytes.len()) as u64))
+            .sum();
+    }
+
+    /// Copy of everything but the recorded deltas, constant time besides the
+    /// deleted prefixes since `kv` is shared.
+    pub(crate) fn without_deltas(&self) -> StoreState {
+        StoreState {
+            kv: self.kv.clone(),
+            block: self.block,
+            deltas: BTreeMap::new(),
+            update_policy: self.update_policy,
+            value_type: self.value_type.clone(),
+            deleted_prefixes: self.deleted_prefixes.clone(),
+            limits: self.limits,
+            total_size: self.total_size,
+            ordinal_check: self.ordinal_check,
+            last_write: self.last_write.clone(),
+            ordinal_violations: self.ordinal_violations.clone(),
+        }
+    }
+}
+
+/// Single threaded handle, this is what `MockStore` and friends use.
+pub type Local = Rc<RefCell<StoreState>>;
+
+/// Thread safe handle, this is what `SyncMockStore` and friends use.
+pub type Shared = Arc<RwLock<StoreState>>;
+
+/// Registries installed on a thread, most recent last, each under its install id.
+pub type InstalledStores<H> = Vec<(u64, HashMap<u32, H>)>;
+
+/// Interior-mutable access to the key/value history of a store.
+///
+/// All the byte level logic lives in the provided methods so the store
+/// trait impls only have to deal with encoding and decoding values.
+pub trait StoreHandle: Clone + Default {
+    fn read(&self) -> impl Deref<Target = StoreState> + '_;
+
+    fn write(&self) -> impl DerefMut<Target = StoreState> + '_;
+
+    /// Runs `f` against this thread's installed store inputs, see
+    /// [`StoreRegistry::install`](crate::mock_store::registry::StoreRegistry::install).
+    fn with_installed<R>(f: impl FnOnce(&mut InstalledStores<Self>) -> R) -> R;
+
+    /// The handle at `idx` in the last registry installed on this thread, or a
+    /// fresh empty one, which is what `StoreGet::new(idx)` used to always return.
+    fn resolve(idx: u32) -> Self {
+        Self::with_installed(|installed| installed.last().and_then(|(_, stores)| stores.get(&idx).cloned()))
+            .unwrap_or_default()
+    }
+
+    /// bytes written at exactly `ord` for `key`
+    fn get_bytes_at(&self, ord: u64, key: &str) -> Option<Vec<u8>> {
+        self.read()
+            .kv
+            .get(key)
+            .and_then(|entries| {
+                entries
+                    .iter()
+                    .find(|(current_ord, _)| *current_ord == ord)
+                    .map(|(_, bytes)| bytes.clone())
+            })
+    }
+
+    fn get_bytes_last(&self, key: &str) -> Option<Vec<u8>> {
+        self.read()
+            .kv
+            .get(key)
+            .and_then(|entries| entries.last().map(|(_, bytes)| bytes.clone()))
+    }
+
+    fn get_bytes_first(&self, key: &str) -> Option<Vec<u8>> {
+        self.read()
+            .kv
+            .get(key)
+            .and_then(|entries| entries.first().map(|(_, bytes)| bytes.clone()))
+    }
+
+    fn has_bytes_at(&self, ord: u64, key: &str) -> bool {
+        self.read()
+            .kv
+            .get(key)
+            .map(|v| v.iter().any(|(v, _)| *v == ord))
+            .unwrap_or(false)
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.read().kv.contains_key(key)
+    }
+
+    /// every key starting with `prefix`, sorted so results don't depend on the hashmap order
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .read()
+            .kv
+            .keys()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Records a new version of `key`, the previous versions are kept around
+    /// so `get_at` and `get_first` still see them.
+    ///
+    /// Also emits the `Create` or `Update` delta the runtime would for that write.
+    /// Panics when the write breaks the store's limits, see the `limits` module.
+    fn push_bytes(&self, ord: u64, key: &str, bytes: Vec<u8>) {
+        self.update_bytes(ord, key, |_| Some(bytes));
+    }
+
+    /// [`StoreHandle::push_bytes`] of what `f` makes of the last value of `key`,
+    /// nothing is written when it returns None.
+    ///
+    /// The read and the write happen under the same guard, so read-modify-writes
+    /// (`add`, `max`, `append`..) racing on a shared handle can't lose updates.
+    fn update_bytes(&self, ord: u64, key: &str, f: impl FnOnce(Option<&[u8]>) -> Option<Vec<u8>>) {
+        let mut guard = self.write();
+
+        let last = guard.kv.get(key).and_then(|entries| entries.last()).map(|(_, bytes)| bytes.as_slice());
+        // f decodes values, which panics on bad bytes, don't poison a shared handle over it
+        let bytes = match panic::catch_unwind(AssertUnwindSafe(|| f(last))) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return,
+            Err(payload) => {
+                drop(guard);
+                panic::resume_unwind(payload);
+            }
+        };
+
+        if let Err(message) = guard.push(ord, key, bytes) {
+            drop(guard); // don't poison a shared handle, a test may catch this
+            panic!("{}", message);
+        }
+    }
+
+    /// Drops every key starting with `prefix`, emitting a `Delete` delta per key
+    /// (sorted by key) with its last value as the old value.
+    fn delete_prefix(&self, ord: u64, prefix: &str) {
+        let mut guard = self.write();
+        let state = &mut *guard;
+
+        if let Err(violation) = state.check_ordinal(prefix, ord) {
+            drop(guard);
+            panic!("{}", violation);
+        }
+
+        let mut keys: Vec<String> = state.kv.keys().filter(|k| k.starts_with(prefix)).cloned().collect();
+        keys.sort();
+
+        for key in keys {
+            state.remove_key(ord, &key);
+        }
+
+        state.deleted_prefixes.push(prefix.to_string());
+    }
+
+    /// Drops exactly `key` with its `Delete` delta, what replaying a delete needs
+    /// since `delete_prefix` would also take the keys it prefixes.
+    fn delete_key(&self, ord: u64, key: &str) {
+        let mut guard = self.write();
+        if let Err(violation) = guard.check_ordinal(key, ord) {
+            drop(guard);
+            panic!("{}", violation);
+        }
+        guard.remove_key(ord, key);
+    }
+
+    fn deleted_prefixes(&self) -> Vec<String> {
+        self.read().deleted_prefixes.clone()
+    }
+
+    fn begin_block(&self, block: u64) {
+        self.write().block = block;
+    }
+
+    fn current_block(&self) -> u64 {
+        self.read().block
+    }
+
+    /// deltas emitted during `block`, in the order the writes happened
+    fn deltas_at(&self, block: u64) -> Vec<StoreDelta> {
+        self.read().deltas.get(&block).cloned().unwrap_or_default()
+    }
+
+    /// every block that recorded deltas, in block order
+    fn recorded_deltas(&self) -> Vec<(u64, Vec<StoreDelta>)> {
+        self.read().deltas.iter().map(|(block, deltas)| (*block, deltas.clone())).collect()
+    }
+}
+
+// per thread so tests running in parallel never see each other's registries,
+// `StoreRegistry::spawn` carries a shared one over to a new thread
+thread_local! {
+    static INSTALLED_LOCAL: RefCell<InstalledStores<Local>> = const { RefCell::new(Vec::new()) };
+    static INSTALLED_SHARED: RefCell<InstalledStores<Shared>> = const { RefCell::new(Vec::new()) };
+}
+
+impl StoreHandle for Local {
+    fn with_installed<R>(f: impl FnOnce(&mut InstalledStores<Self>) -> R) -> R {
+        INSTALLED_LOCAL.with(|installed| f(&mut installed.borrow_mut()))
+    }
+
+    fn read(&self) -> impl Deref<Target = StoreState> + '_ {
+        self.borrow()
+    }
+
+    fn write(&self) -> impl DerefMut<Target = StoreState> + '_ {
+        self.borrow_mut()
+    }
+}
+
+impl StoreHandle for Shared {
+    fn with_installed<R>(f: impl FnOnce(&mut InstalledStores<Self>) -> R) -> R {
+        INSTALLED_SHARED.with(|installed| f(&mut installed.borrow_mut()))
+    }
+
+    // a poisoned lock means another test thread panicked mid write, the data can't be trusted anymore
+    fn read(&self) -> impl Deref<Target = StoreState> + '_ {
+        RwLock::read(self).expect("mock store lock poisoned")
+    }
+
+    fn write(&self) -> impl DerefMut<Target = StoreState> + '_ {
+        RwLock::write(self).expect("mock store lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delete_prefix_emits_a_delete_per_key_sorted() {
+        let handle = Local::default();
+        handle.push_bytes(1, "pool:0xcd", b"2".to_vec());
+        handle.push_bytes(2, "pool:0xab", b"1".to_vec());
+        handle.push_bytes(3, "pool:0xab", b"3".to_vec());
+        handle.push_bytes(4, "token:0xef", b"4".to_vec());
+        handle.delete_prefix(5, "pool:");
+
+        let deletes: Vec<(String, Vec<u8>)> = handle.deltas_at(0)[4..]
+            .iter()
+            .map(|delta| {
+                assert_eq!(delta.operation, Operation::Delete as i32);
+                (delta.key.clone(), delta.old_value.clone())
+            })
+            .collect();
+        assert_eq!(deletes, vec![("pool:0xab".to_string(), b"3".to_vec()), ("pool:0xcd".to_string(), b"2".to_vec())]);
+        assert_eq!(handle.keys_with_prefix(""), vec!["token:0xef"]);
+        assert_eq!(handle.deleted_prefixes(), vec!["pool:"]);
+        assert_eq!(handle.read().total_size, ("token:0xef".len() + 1) as u64);
+    }
+
+    #[test]
+    fn delete_key_leaves_the_keys_it_prefixes() {
+        let handle = Local::default();
+        handle.push_bytes(1, "pool", b"1".to_vec());
+        handle.push_bytes(2, "pool:0xab", b"2".to_vec());
+        handle.delete_key(3, "pool");
+        handle.delete_key(4, "missing");
+
+        assert_eq!(handle.keys_with_prefix(""), vec!["pool:0xab"]);
+        assert_eq!(handle.deltas_at(0).len(), 3);
+        assert!(handle.deleted_prefixes().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+//! Runs a store handler over fixture blocks.
+//!
+//! Every test used to rebuild the same loop around `MockStore::new()`, this does it
+//! once: blocks are fed to the handler in order, each one under its own block
+//! number so the deltas it produces can be looked at per block afterwards.
+//!
+//! ```no_run
+//! # use substreams::{prelude::*, scalar::BigInt, store::{DeltaBigInt, Deltas}};
+//! # use Stores_and_Deltas::mock_store::{harness::StoreHarness, store::MockStore};
+//! # #[derive(Clone, PartialEq, prost::Message)]
+//! # struct Block {}
+//! # fn store_volumes(_block: Block, _store: MockStore) {}
+//! # let blocks: Vec<Block> = Vec::new();
+//! let run = StoreHarness::new(<MockStore as StoreNew>::new())
+//!     .starting_at(12_000_000)
+//!     .run(&blocks, |block: &Block, store| store_volumes(block.clone(), store.clone()));
+//!
+//! let volume: Option<BigInt> = run.store.get_last("volume");
+//! let deltas: Deltas<DeltaBigInt> = run.deltas(12_000_001);
+//! ```
+//!
+//! [`StoreHarness::assert_deterministic`] runs the handler twice from the same
+//! initial state and fails if the two runs end up with different stores or deltas,
+//! non-deterministic store modules break parallel backprocessing.
+use prost::Message;
+use substreams::{
+    pb::substreams::StoreDelta,
+    store::{Delta, Deltas},
+};
+use crate::mock_store::{
+    delta::DeltaRecorder,
+    fingerprint::StoreFingerprint,
+    handle::StoreHandle,
+    store::HasHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct StoreHarness<S> {
+    store: S,
+    start_block: u64,
+}
+
+impl<S: HasHandle> StoreHarness<S> {
+    /// `store` is the initial state, seed it before handing it over.
+    pub fn new(store: S) -> Self {
+        Self { store, start_block: 0 }
+    }
+
+    /// Block number given to the first block, the next ones follow from it. Defaults to 0.
+    pub fn starting_at(mut self, start_block: u64) -> Self {
+        self.start_block = start_block;
+        self
+    }
+
+    /// Feeds `blocks` to `handler` in order and collects what each block wrote,
+    /// the deltas of the writes seeding the store are left out.
+    pub fn run<B, F>(self, blocks: &[B], mut handler: F) -> HarnessRun<S>
+    where
+        B: Message,
+        F: FnMut(&B, &S),
+    {
+        let mut deltas = Vec::with_capacity(blocks.len());
+        // seeding writes aren't part of the first block, its ordinals start over
+        self.store.handle().write().last_write = None;
+
+        for (block_num, block) in (self.start_block..).zip(blocks) {
+            self.store.begin_block(block_num);
+            // the store may already hold deltas for this block, seeding records under block 0 too
+            let seeded = self.store.store_deltas(block_num).len();
+            handler(block, &self.store);
+
+            let mut written = self.store.store_deltas(block_num);
+            written.drain(..seeded);
+            deltas.push((block_num, written));
+        }
+
+        HarnessRun { store: self.store, deltas }
+    }
+
+    /// [`StoreHarness::run`] twice over copies of the initial store, panics when the
+    /// final fingerprints or the deltas of any block differ. Returns the first run.
+    ///
+    /// What this catches is nondeterminism inside the handler: state it carries
+    /// from one call to the next, randomness, clocks, globals, iterating its own
+    /// hash maps. The store can't be a source of it, `StoreScan` always returns
+    /// keys sorted.
+    #[track_caller]
+    pub fn assert_deterministic<B, F>(self, blocks: &[B], mut handler: F) -> HarnessRun<S>
+    where
+        B: Message,
+        F: FnMut(&B, &S),
+    {
+        let second = Self { store: duplicate(&self.store), start_block: self.start_block };
+        let first = Self { store: duplicate(&self.store), start_block: self.start_block };
+
+        let first = first.run(blocks, &mut handler);
+        let second = second.run(blocks, &mut handler);
+
+        let diverged = first.deltas.iter().zip(&second.deltas).find(|(a, b)| a != b);
+        if let Some(((block_num, a), (_, b))) = diverged {
+            // only the first differing delta, whole blocks get long
+            let idx = a.iter().zip(b).position(|(x, y)| x != y).unwrap_or(a.len().min(b.len()));
+            panic!(
+                "handler is not deterministic, deltas of block {} differ at #{} ({} vs {} deltas):\n  first run:  {:?}\n  second run: {:?}",
+                block_num,
+                idx,
+                a.len(),
+                b.len(),
+                a.get(idx),
+                b.get(idx)
+            );
+        }
+
+        let (a, b) = (first.store.fingerprint(), second.store.fingerprint());
+        if a != b {
+            panic!("handler is not deterministic, store fingerprints differ:\n  first run:  {}\n  second run: {}", a, b);
+        }
+
+        first
+    }
+}
+
+// copy of the state, a clone of the store would share its handle
+fn duplicate<S: HasHandle>(store: &S) -> S {
+    let copy = S::from_handle(Default::default());
+    *copy.handle().write() = store.handle().read().clone();
+    copy
+}
+
+/// Final store and the deltas of every block a [`StoreHarness`] ran.
+#[derive(Debug, Clone)]
+pub struct HarnessRun<S> {
+    pub store: S,
+    /// (block number, deltas emitted during that block), one entry per input block
+    pub deltas: Vec<(u64, Vec<StoreDelta>)>,
+}
+
+impl<S> HarnessRun<S> {
+    /// Raw deltas of `block`, empty if the block wasn't part of the run.
+    pub fn store_deltas(&self, block: u64) -> Vec<StoreDelta> {
+        self.deltas
+            .iter()
+            .find(|(block_num, _)| *block_num == block)
+            .map(|(_, deltas)| deltas.clone())
+            .unwrap_or_default()
+    }
+
+    /// What a `mode: deltas` consumer of the store would receive for `block`.
+    pub fn deltas<D: Delta + From<StoreDelta>>(&self, block: u64) -> Deltas<D> {
+        Deltas::new(self.store_deltas(block))
+    }
+
+    /// Block numbers of the run, in order.
+    pub fn blocks(&self) -> impl Iterator<Item = u64> + '_ {
+        self.deltas.iter().map(|(block_num, _)| *block_num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::store::MockStore;
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    struct Block {
+        #[prost(uint64, tag = "1")]
+        number: u64,
+    }
+
+    fn keys(deltas: &[StoreDelta]) -> Vec<&str> {
+        deltas.iter().map(|delta| delta.key.as_str()).collect()
+    }
+
+    #[test]
+    fn seeding_deltas_are_not_reported() {
+        let store = <MockStore as StoreNew>::new();
+        store.set(5, "seeded", &1i64);
+
+        let run = StoreHarness::new(store).run(&[Block { number: 1 }], |block, store: &MockStore| {
+            store.set(1, "k", &(block.number as i64));
+        });
+
+        assert_eq!(keys(&run.store_deltas(0)), ["k"]);
+        assert_eq!(run.store.get_last("seeded"), Some(1i64));
+    }
+
+    #[test]
+    fn deltas_are_recorded_per_block() {
+        let blocks = [Block { number: 10 }, Block { number: 11 }];
+        let run = StoreHarness::new(<MockStore as StoreNew>::new())
+            .starting_at(10)
+            .run(&blocks, |block, store: &MockStore| store.add(1, format!("b{}", block.number), 1i64));
+
+        assert_eq!(run.blocks().collect::<Vec<_>>(), [10, 11]);
+        assert_eq!(keys(&run.store_deltas(10)), ["b10"]);
+        assert_eq!(keys(&run.store_deltas(11)), ["b11"]);
+    }
+
+    #[test]
+    fn deterministic_handler_passes() {
+        let blocks = [Block { number: 1 }, Block { number: 2 }];
+        StoreHarness::new(<MockStore as StoreNew>::new()).assert_deterministic(&blocks, |block, store: &MockStore| {
+            store.add(block.number, "total", block.number as i64);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "handler is not deterministic")]
+    fn state_carried_between_calls_is_caught() {
+        // the count keeps going in the second run, so it writes other values
+        let mut calls = 0i64;
+        StoreHarness::new(<MockStore as StoreNew>::new()).assert_deterministic(&[Block { number: 1 }], |_, store: &MockStore| {
+            calls += 1;
+            store.set(1, "calls", &calls);
+        });
+    }
+}
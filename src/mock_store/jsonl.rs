@@ -0,0 +1,234 @@
+//! Store deltas as JSON Lines, one delta per line.
+//!
+//! ```text
+//! {"operation":"create","ordinal":1,"key":"volume","new_value":"1200"}
+//! {"operation":"update","ordinal":7,"key":"volume","old_value":"1200","new_value":"1300"}
+//! {"operation":"delete","ordinal":9,"key":"volume","old_value":"1300"}
+//! ```
+//!
+//! Values go through [`ValueType::decode_json`]/[`ValueType::encode_json`] so they
+//! read like the values they stand for. Without a value type there is no telling
+//! text from bytes, so values are always `0x` hex, text included.
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use substreams::pb::substreams::{store_delta::Operation, StoreDelta};
+use crate::mock_store::value_type::ValueType;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonlError {
+    /// 1 based, like editors show them
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for JsonlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for JsonlError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonDelta {
+    /// only set by exports covering several blocks, see [`read_block_deltas`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<u64>,
+    /// `create`, `update` or `delete`
+    pub operation: String,
+    pub ordinal: u64,
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub old_value: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub new_value: Value,
+}
+
+impl JsonDelta {
+    pub fn from_delta(delta: &StoreDelta, value_type: Option<&ValueType>) -> Self {
+        Self::from_delta_with(delta, |bytes| decode_value(value_type, bytes))
+    }
+
+    /// [`JsonDelta::from_delta`] with a custom decoding of the values, proto exports use it.
+    pub fn from_delta_with(delta: &StoreDelta, decode: impl Fn(&[u8]) -> Value) -> Self {
+        let operation = match Operation::try_from(delta.operation).unwrap_or(Operation::Unset) {
+            Operation::Create => "create",
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+            Operation::Unset => "unset",
+        };
+        // creates have no old value and deletes no new one, leave them out instead of ""
+        let value = |bytes: &[u8], present: bool| if present { decode(bytes) } else { Value::Null };
+
+        JsonDelta {
+            block: None,
+            operation: operation.to_string(),
+            ordinal: delta.ordinal,
+            key: delta.key.clone(),
+            old_value: value(&delta.old_value, operation != "create"),
+            new_value: value(&delta.new_value, operation != "delete"),
+        }
+    }
+
+    pub fn to_delta(&self, value_type: Option<&ValueType>) -> Result<StoreDelta, String> {
+        self.to_delta_with(|value| encode_value(value_type, value))
+    }
+
+    /// [`JsonDelta::to_delta`] with a custom encoding of the values, proto imports use it.
+    pub fn to_delta_with(&self, encode: impl Fn(&Value) -> Result<Vec<u8>, String>) -> Result<StoreDelta, String> {
+        let operation = match self.operation.as_str() {
+            "create" => Operation::Create,
+            "update" => Operation::Update,
+            "delete" => Operation::Delete,
+            other => return Err(format!("unknown operation {}", other)),
+        };
+        let value = |value: &Value| if value.is_null() { Ok(Vec::new()) } else { encode(value) };
+
+        Ok(StoreDelta {
+            operation: operation as i32,
+            ordinal: self.ordinal,
+            key: self.key.clone(),
+            old_value: value(&self.old_value)?,
+            new_value: value(&self.new_value)?,
+        })
+    }
+}
+
+/// One JSON object per delta, in order.
+pub fn write_deltas(deltas: &[StoreDelta], value_type: Option<&ValueType>) -> String {
+    deltas
+        .iter()
+        .map(|delta| {
+            let line = serde_json::to_string(&JsonDelta::from_delta(delta, value_type)).expect("json deltas always serialize");
+            line + "\n"
+        })
+        .collect()
+}
+
+/// Parses the output of [`write_deltas`], blank lines are skipped.
+pub fn read_deltas(content: &str, value_type: Option<&ValueType>) -> Result<Vec<StoreDelta>, JsonlError> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            serde_json::from_str::<JsonDelta>(line)
+                .map_err(|e| e.to_string())
+                .and_then(|delta| delta.to_delta(value_type))
+                .map_err(|reason| JsonlError { line: idx + 1, reason })
+        })
+        .collect()
+}
+
+/// [`read_deltas`] grouped by block, for [`Snapshot::apply`](crate::mock_store::snapshot::Snapshot::apply).
+///
+/// Consecutive lines with the same `block` make one group, lines without one
+/// belong to `block`. Blocks can't go backwards.
+pub fn read_block_deltas(
+    content: &str,
+    value_type: Option<&ValueType>,
+    block: u64,
+) -> Result<Vec<(u64, Vec<StoreDelta>)>, JsonlError> {
+    read_block_deltas_with(content, block, |value| encode_value(value_type, value))
+}
+
+/// [`read_block_deltas`] with a custom encoding of the values, see [`JsonDelta::to_delta_with`].
+pub fn read_block_deltas_with(
+    content: &str,
+    block: u64,
+    encode: impl Fn(&Value) -> Result<Vec<u8>, String>,
+) -> Result<Vec<(u64, Vec<StoreDelta>)>, JsonlError> {
+    let mut blocks: Vec<(u64, Vec<StoreDelta>)> = Vec::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let err = |reason: String| JsonlError { line: idx + 1, reason };
+
+        let json_delta = serde_json::from_str::<JsonDelta>(line).map_err(|e| err(e.to_string()))?;
+        let delta = json_delta.to_delta_with(&encode).map_err(err)?;
+        let line_block = json_delta.block.unwrap_or(block);
+
+        match blocks.last_mut() {
+            Some((current, deltas)) if *current == line_block => deltas.push(delta),
+            Some((current, _)) if *current > line_block => {
+                return Err(err(format!("block {} comes after block {}", line_block, current)));
+            }
+            _ => blocks.push((line_block, vec![delta])),
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// How values are written without a custom decoding, see the module docs.
+pub fn decode_value(value_type: Option<&ValueType>, bytes: &[u8]) -> Value {
+    match value_type {
+        Some(value_type) => value_type.decode_json(bytes),
+        None => Value::from(format!("0x{}", hex::encode(bytes))),
+    }
+}
+
+/// The other way around from [`decode_value`].
+pub(crate) fn encode_value(value_type: Option<&ValueType>, value: &Value) -> Result<Vec<u8>, String> {
+    match (value_type, value) {
+        // protos can't be encoded from JSON without their type, decode_json wrote them as hex
+        (Some(ValueType::Proto(_)), Value::String(s)) => {
+            hex::decode(s.trim_start_matches("0x")).map_err(|e| format!("invalid hex bytes: {}", e))
+        }
+        (Some(value_type), value) => value_type.encode_json(value),
+        (None, Value::String(s)) => match s.strip_prefix("0x") {
+            Some(digits) => hex::decode(digits).map_err(|e| format!("invalid hex bytes: {}", e)),
+            None => Err(format!("expected a 0x hex string without a value type, got {:?}", s)),
+        },
+        (None, other) => Err(format!("expected a 0x hex string without a value type, got {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_grouped_by_block() {
+        let content = r#"{"operation":"create","ordinal":1,"key":"a","new_value":"1"}
+{"block":4,"operation":"create","ordinal":1,"key":"b","new_value":"2"}
+{"block":4,"operation":"update","ordinal":2,"key":"b","old_value":"2","new_value":"3"}
+"#;
+        let blocks = read_block_deltas(content, Some(&ValueType::Int64), 3).unwrap();
+
+        let keys: Vec<(u64, Vec<&str>)> = blocks
+            .iter()
+            .map(|(block, deltas)| (*block, deltas.iter().map(|d| d.key.as_str()).collect()))
+            .collect();
+        assert_eq!(keys, vec![(3, vec!["a"]), (4, vec!["b", "b"])]);
+    }
+
+    #[test]
+    fn values_without_a_value_type_round_trip_as_hex() {
+        let delta = StoreDelta {
+            operation: Operation::Create as i32,
+            ordinal: 1,
+            key: "a".to_string(),
+            old_value: Vec::new(),
+            // text that looks like hex must come back as the same text
+            new_value: b"0xabc1".to_vec(),
+        };
+
+        let content = write_deltas(std::slice::from_ref(&delta), None);
+        assert!(content.contains(r#""new_value":"0x307861626331""#));
+        assert_eq!(read_deltas(&content, None).unwrap(), vec![delta]);
+        assert!(read_deltas(r#"{"operation":"create","ordinal":1,"key":"a","new_value":"10"}"#, None).is_err());
+    }
+
+    #[test]
+    fn blocks_cannot_go_backwards() {
+        let content = r#"{"block":4,"operation":"create","ordinal":1,"key":"a","new_value":"1"}
+{"block":3,"operation":"create","ordinal":1,"key":"b","new_value":"2"}
+"#;
+        let err = read_block_deltas(content, Some(&ValueType::Int64), 0).unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+}
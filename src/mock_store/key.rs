@@ -1,6 +1,4 @@
 // https://github.com/streamingfast/substreams-rs/blob/ebaf5ebe0c03313fd3cfb144080f138f81367887/substreams/src/key.rs#L1
-use substreams::{store::Delta};
-use std::io::BufRead;
 
 //The only relevant thing i changed here was removing the use of std::io::Cursor will just keep it here lol
 
@@ -24,4 +22,34 @@ pub fn segment_at_owned(key: String, index: usize) -> String {
         .expect("Must be valid UTF-8 here as we split an initially valid String")
 }
 
+// the rest are the same as upstream but take &str, clippy doesn't like &String
 
+pub fn segment_at(key: &str, index: usize) -> &str {
+    try_segment_at(key, index).unwrap_or_else(|| {
+        panic!(
+            "Unable to extract segment index {} out of key {}",
+            index, key
+        )
+    })
+}
+
+pub fn first_segment(key: &str) -> &str {
+    segment_at(key, 0)
+}
+
+pub fn last_segment(key: &str) -> &str {
+    try_last_segment(key)
+        .unwrap_or_else(|| panic!("Unable to extract last segment out of key {}", key))
+}
+
+pub fn try_segment_at(key: &str, index: usize) -> Option<&str> {
+    key.split(':').nth(index)
+}
+
+pub fn try_first_segment(key: &str) -> Option<&str> {
+    try_segment_at(key, 0)
+}
+
+pub fn try_last_segment(key: &str) -> Option<&str> {
+    key.rsplit(':').next()
+}
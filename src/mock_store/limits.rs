@@ -0,0 +1,117 @@
+//! Key and value limits checked on every write.
+//!
+//! The runtime aborts a module writing an oversized value or growing a store past
+//! its size limit, the mocks used to accept anything so tests passed where
+//! production failed. Every write now goes through [`StoreLimits::check`] and
+//! panics, like the runtime aborts, with a message naming the key and the limit.
+//!
+//! ```no_run
+//! # use Stores_and_Deltas::mock_store::{limits::StoreLimits, store::{MockStore, StoreConfig}};
+//! # let store = MockStore::default();
+//! store.set_limits(StoreLimits { max_value_size: 1024, ..StoreLimits::default() });
+//! ```
+//!
+//! Keys are `&str` so they are always valid UTF-8, only emptiness and length are checked.
+use std::fmt;
+
+/// Largest value the runtime accepts for a single key, 10 MiB.
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 10 * 1024 * 1024;
+
+/// Largest total size (keys and last values) of a store in the runtime, 1 GiB.
+pub const DEFAULT_MAX_TOTAL_SIZE: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreLimits {
+    pub max_value_size: usize,
+    /// the runtime has no key length limit, so none by default, set one to catch runaway keys
+    pub max_key_length: usize,
+    /// sum of the key and last value lengths over every key
+    pub max_total_size: u64,
+}
+
+impl Default for StoreLimits {
+    fn default() -> Self {
+        Self {
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+            max_key_length: usize::MAX,
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    EmptyKey,
+    KeyTooLong { key: String, length: usize, limit: usize },
+    ValueTooLarge { key: String, size: usize, limit: usize },
+    StoreTooLarge { key: String, size: u64, limit: u64 },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::EmptyKey => write!(f, "store keys cannot be empty"),
+            LimitError::KeyTooLong { key, length, limit } => {
+                // the key itself could be huge, only show its start
+                let start: String = key.chars().take(64).collect();
+                write!(f, "key {:?}.. is {} bytes long, over the max_key_length limit of {}", start, length, limit)
+            }
+            LimitError::ValueTooLarge { key, size, limit } => {
+                write!(f, "value for key {:?} is {} bytes, over the max_value_size limit of {}", key, size, limit)
+            }
+            LimitError::StoreTooLarge { key, size, limit } => write!(
+                f,
+                "writing key {:?} grows the store to {} bytes, over the max_total_size limit of {}",
+                key, size, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl StoreLimits {
+    /// No limits at all, what the mocks did before.
+    pub fn unlimited() -> Self {
+        Self { max_value_size: usize::MAX, max_key_length: usize::MAX, max_total_size: u64::MAX }
+    }
+
+    /// Checks a write of `value_size` bytes to `key` leaving the store at `total_size` bytes.
+    pub fn check(&self, key: &str, value_size: usize, total_size: u64) -> Result<(), LimitError> {
+        if key.is_empty() {
+            return Err(LimitError::EmptyKey);
+        }
+        if key.len() > self.max_key_length {
+            return Err(LimitError::KeyTooLong { key: key.to_string(), length: key.len(), limit: self.max_key_length });
+        }
+        if value_size > self.max_value_size {
+            return Err(LimitError::ValueTooLarge { key: key.to_string(), size: value_size, limit: self.max_value_size });
+        }
+        if total_size > self.max_total_size {
+            return Err(LimitError::StoreTooLarge { key: key.to_string(), size: total_size, limit: self.max_total_size });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_have_no_length_limit_by_default() {
+        let key = "k".repeat(1024 * 1024);
+        assert_eq!(StoreLimits::default().check(&key, 1, 0), Ok(()));
+        assert_eq!(StoreLimits::default().check("", 1, 0), Err(LimitError::EmptyKey));
+    }
+
+    #[test]
+    fn values_over_the_limit_are_rejected() {
+        let limits = StoreLimits { max_value_size: 4, ..StoreLimits::default() };
+        assert_eq!(limits.check("volume", 4, 0), Ok(()));
+        assert_eq!(
+            limits.check("volume", 5, 0),
+            Err(LimitError::ValueTooLarge { key: "volume".to_string(), size: 5, limit: 4 })
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Builds the mock stores of a package straight from its `substreams.yaml`.
+//!
+//! Only the store modules are looked at: their `updatePolicy` and `valueType` are
+//! parsed and an empty, configured mock store is created for each one, keyed by
+//! module name, so tests don't have to repeat what the manifest already says.
+//! The `get` mode store inputs of every module are kept too, their order is the
+//! `idx` the runtime hands that module's `StoreGet::new`.
+//!
+//! ```no_run
+//! # use Stores_and_Deltas::mock_store::{manifest::Manifest, store::{MockProtoStore, MockStore}};
+//! # #[derive(Clone, PartialEq, ::prost::Message)]
+//! # struct Pool {}
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let manifest = Manifest::from_file("substreams.yaml")?;
+//! let stores = manifest.mock_stores();
+//!
+//! let balances: MockStore = stores.get("store_balances").unwrap();
+//! let pools: MockProtoStore<Pool> = stores.get("store_pools").unwrap();
+//!
+//! // StoreGet::new(idx) now sees what map_prices reads
+//! let _installed = manifest.registry_for("map_prices", &stores).unwrap().install();
+//! # Ok(())
+//! # }
+//! ```
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
+use serde::Deserialize;
+use substreams::pb::substreams::module::kind_store::UpdatePolicy;
+use crate::mock_store::{
+    handle::StoreHandle,
+    registry::StoreRegistry,
+    store::{BaseMockStore, StoreConfig},
+    value_type::ValueType,
+};
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(io::Error),
+    Yaml(serde_yaml::Error),
+    MissingField { module: String, field: &'static str },
+    InvalidField { module: String, field: &'static str, reason: String },
+    UnknownModule(String),
+    /// a module reads a store the registry doesn't have
+    UnknownStore { module: String, store: String },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(e) => write!(f, "cannot read manifest: {}", e),
+            ManifestError::Yaml(e) => write!(f, "invalid manifest: {}", e),
+            ManifestError::MissingField { module, field } => {
+                write!(f, "store module {} has no {}", module, field)
+            }
+            ManifestError::InvalidField { module, field, reason } => {
+                write!(f, "store module {} has an invalid {}: {}", module, field, reason)
+            }
+            ManifestError::UnknownModule(module) => write!(f, "no module named {}", module),
+            ManifestError::UnknownStore { module, store } => {
+                write!(f, "module {} reads store {} which isn't registered", module, store)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+impl From<io::Error> for ManifestError {
+    fn from(e: io::Error) -> Self {
+        ManifestError::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for ManifestError {
+    fn from(e: serde_yaml::Error) -> Self {
+        ManifestError::Yaml(e)
+    }
+}
+
+// only what we need from the manifest, serde ignores the rest
+#[derive(Deserialize)]
+struct RawManifest {
+    #[serde(default)]
+    modules: Vec<RawModule>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawModule {
+    name: String,
+    kind: String,
+    update_policy: Option<String>,
+    value_type: Option<String>,
+    initial_block: Option<u64>,
+    #[serde(default)]
+    inputs: Vec<RawInput>,
+}
+
+#[derive(Deserialize)]
+struct RawInput {
+    store: Option<String>,
+    mode: Option<String>,
+}
+
+/// A store module as declared in the manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreModule {
+    pub name: String,
+    pub update_policy: UpdatePolicy,
+    pub value_type: ValueType,
+    pub initial_block: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// store modules, in manifest order
+    pub stores: Vec<StoreModule>,
+    /// module name -> the stores it reads in `get` mode, in input order
+    pub store_inputs: BTreeMap<String, Vec<String>>,
+}
+
+impl Manifest {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ManifestError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(yaml: &str) -> Result<Self, ManifestError> {
+        let mut raw: RawManifest = serde_yaml::from_str(yaml)?;
+
+        let store_inputs = raw
+            .modules
+            .iter_mut()
+            .map(|module| {
+                let inputs = std::mem::take(&mut module.inputs)
+                    .into_iter()
+                    // the mode defaults to get, like in the manifest
+                    .filter(|input| input.mode.as_deref().is_none_or(|mode| mode == "get"))
+                    .filter_map(|input| input.store)
+                    .collect();
+                (module.name.clone(), inputs)
+            })
+            .collect();
+
+        let stores = raw
+            .modules
+            .into_iter()
+            .filter(|module| module.kind == "store")
+            .map(StoreModule::try_from)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { stores, store_inputs })
+    }
+
+    pub fn store(&self, name: &str) -> Option<&StoreModule> {
+        self.stores.iter().find(|store| store.name == name)
+    }
+
+    /// One empty mock store per store module, configured with its update policy and
+    /// value type and registered under the module name.
+    ///
+    /// Its indices are just manifest order, install [`Manifest::registry_for`] to
+    /// run a handler.
+    pub fn mock_stores<H: StoreHandle>(&self) -> StoreRegistry<H> {
+        let mut registry = StoreRegistry::new();
+
+        for module in &self.stores {
+            // the state is shared by every store kind, so creating it as a MockStore is fine
+            let store: BaseMockStore<H> = registry.create(&module.name);
+            store.configure(Some(module.update_policy), Some(module.value_type.clone()));
+        }
+
+        registry
+    }
+
+    /// The stores `module` reads in `get` mode, taken from `stores` and indexed
+    /// the way the runtime indexes them for that module.
+    pub fn registry_for<H: StoreHandle>(
+        &self,
+        module: &str,
+        stores: &StoreRegistry<H>,
+    ) -> Result<StoreRegistry<H>, ManifestError> {
+        let inputs = self
+            .store_inputs
+            .get(module)
+            .ok_or_else(|| ManifestError::UnknownModule(module.to_string()))?;
+
+        let mut registry = StoreRegistry::new();
+        for store_name in inputs {
+            let store: BaseMockStore<H> = stores.get(store_name).ok_or_else(|| ManifestError::UnknownStore {
+                module: module.to_string(),
+                store: store_name.clone(),
+            })?;
+            registry.register(store_name, &store);
+        }
+        Ok(registry)
+    }
+}
+
+impl TryFrom<RawModule> for StoreModule {
+    type Error = ManifestError;
+
+    fn try_from(raw: RawModule) -> Result<Self, Self::Error> {
+        let update_policy = raw.update_policy.as_deref().ok_or_else(|| ManifestError::MissingField {
+            module: raw.name.clone(),
+            field: "updatePolicy",
+        })?;
+        let value_type = raw.value_type.as_deref().ok_or_else(|| ManifestError::MissingField {
+            module: raw.name.clone(),
+            field: "valueType",
+        })?;
+
+        Ok(StoreModule {
+            update_policy: parse_update_policy(update_policy).map_err(|reason| ManifestError::InvalidField {
+                module: raw.name.clone(),
+                field: "updatePolicy",
+                reason,
+            })?,
+            value_type: value_type.parse().map_err(|reason| ManifestError::InvalidField {
+                module: raw.name.clone(),
+                field: "valueType",
+                reason,
+            })?,
+            initial_block: raw.initial_block,
+            name: raw.name,
+        })
+    }
+}
+
+fn parse_update_policy(policy: &str) -> Result<UpdatePolicy, String> {
+    match policy {
+        "set" => Ok(UpdatePolicy::Set),
+        "set_if_not_exists" => Ok(UpdatePolicy::SetIfNotExists),
+        "add" => Ok(UpdatePolicy::Add),
+        "min" => Ok(UpdatePolicy::Min),
+        "max" => Ok(UpdatePolicy::Max),
+        "append" => Ok(UpdatePolicy::Append),
+        _ => Err(format!("unknown update policy {}", policy)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_store::store::MockStore;
+
+    const MANIFEST: &str = r#"
+specVersion: v0.1.0
+modules:
+  - name: map_transfers
+    kind: map
+    output:
+      type: proto:transfers.v1.Transfers
+  - name: store_balances
+    kind: store
+    updatePolicy: add
+    valueType: bigint
+    initialBlock: 12
+  - name: store_pools
+    kind: store
+    updatePolicy: set
+    valueType: proto:pools.v1.Pool
+  - name: map_prices
+    kind: map
+    inputs:
+      - source: sf.ethereum.type.v2.Block
+      - store: store_balances
+        mode: deltas
+      - store: store_pools
+      - store: store_balances
+        mode: get
+"#;
+
+    #[test]
+    fn store_modules_are_parsed_in_order() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let names: Vec<&str> = manifest.stores.iter().map(|store| store.name.as_str()).collect();
+        assert_eq!(names, vec!["store_balances", "store_pools"]);
+
+        let balances = manifest.store("store_balances").unwrap();
+        assert_eq!(balances.update_policy, UpdatePolicy::Add);
+        assert_eq!(balances.value_type, ValueType::BigInt);
+        assert_eq!(balances.initial_block, Some(12));
+        assert_eq!(manifest.store("store_pools").unwrap().value_type, ValueType::Proto("pools.v1.Pool".to_string()));
+    }
+
+    #[test]
+    fn mock_stores_are_configured_from_the_manifest() {
+        let stores = Manifest::parse(MANIFEST).unwrap().mock_stores();
+        let balances: MockStore = stores.get("store_balances").unwrap();
+
+        assert_eq!(stores.index_of("store_pools"), Some(1));
+        assert_eq!(balances.update_policy(), Some(UpdatePolicy::Add));
+        assert_eq!(balances.value_type(), Some(ValueType::BigInt));
+    }
+
+    #[test]
+    fn registries_are_indexed_per_module() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let stores = manifest.mock_stores::<crate::mock_store::handle::Local>();
+
+        let registry = manifest.registry_for("map_prices", &stores).unwrap();
+        assert_eq!(registry.name_of(0), Some("store_pools"));
+        assert_eq!(registry.name_of(1), Some("store_balances"));
+        assert_eq!(registry.len(), 2);
+
+        assert!(manifest.registry_for("store_pools", &stores).unwrap().is_empty());
+        assert!(matches!(manifest.registry_for("map_missing", &stores), Err(ManifestError::UnknownModule(_))));
+    }
+
+    #[test]
+    fn invalid_store_modules_are_reported() {
+        let missing = "modules:\n  - name: store_x\n    kind: store\n    updatePolicy: set\n";
+        assert!(matches!(
+            Manifest::parse(missing),
+            Err(ManifestError::MissingField { field: "valueType", .. })
+        ));
+
+        let invalid = "modules:\n  - name: store_x\n    kind: store\n    updatePolicy: sum\n    valueType: int64\n";
+        assert!(matches!(
+            Manifest::parse(invalid),
+            Err(ManifestError::InvalidField { field: "updatePolicy", .. })
+        ));
+    }
+}
@@ -3,3 +3,27 @@ pub mod delta;
 pub mod traits;
 pub mod proto;
 pub mod key;
+pub mod handle;
+pub mod registry;
+pub mod view;
+pub mod harness;
+pub mod executor;
+pub mod value_type;
+pub mod manifest;
+pub mod assertions;
+pub mod fixture;
+pub mod format;
+pub mod text_format;
+pub mod golden;
+pub mod dump;
+pub mod snapshot;
+pub mod jsonl;
+pub mod export;
+pub mod database_changes;
+pub mod entity_changes;
+pub mod delta_ops;
+pub mod limits;
+pub mod ordinal;
+pub mod fingerprint;
+pub mod fork;
+pub mod checkpoint;
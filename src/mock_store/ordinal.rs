@@ -0,0 +1,145 @@
+//! Ordinal ordering checks.
+//!
+//! Within a block, the ordinals of a store's writes have to be non-decreasing,
+//! the runtime applies them in that order. Handlers writing with the wrong
+//! ordinal (a log index from another transaction, a stale counter..) are a
+//! common bug the mocks wouldn't notice, so once turned on every write is
+//! checked against the last one of the same block. Stores start with the check
+//! off, a lenient check records violations on the store for tests to look at.
+//!
+//! ```no_run
+//! # use substreams::prelude::*;
+//! # use Stores_and_Deltas::mock_store::{ordinal::OrdinalCheck, store::{MockStore, StoreConfig}};
+//! # let store = <MockStore as StoreNew>::new();
+//! store.set_ordinal_check(OrdinalCheck::Strict);
+//! ```
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OrdinalCheck {
+    #[default]
+    Off,
+    /// records the violation, see `StoreConfig::ordinal_violations`, and keeps going
+    Lenient,
+    /// panics on the offending write
+    Strict,
+}
+
+/// A write whose ordinal is lower than the previous write of the same block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrdinalViolation {
+    pub block: u64,
+    pub key: String,
+    pub ordinal: u64,
+    pub previous_key: String,
+    pub previous_ordinal: u64,
+}
+
+impl fmt::Display for OrdinalViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "out of order write in block {}: key {:?} at ordinal {} comes after key {:?} at ordinal {}",
+            self.block, self.key, self.ordinal, self.previous_key, self.previous_ordinal
+        )
+    }
+}
+
+/// Last write of the current block, what the next one is checked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LastWrite {
+    pub(crate) block: u64,
+    pub(crate) key: String,
+    pub(crate) ordinal: u64,
+}
+
+impl OrdinalCheck {
+    /// Records a write of `key` at `ordinal`, returns the violation if it has to be reported.
+    ///
+    /// A strict violation isn't recorded, the write panics and never happens.
+    pub(crate) fn record(&self, last: &mut Option<LastWrite>, block: u64, key: &str, ordinal: u64) -> Option<OrdinalViolation> {
+        let violation = match last {
+            Some(previous) if previous.block == block && ordinal < previous.ordinal => Some(OrdinalViolation {
+                block,
+                key: key.to_string(),
+                ordinal,
+                previous_key: previous.key.clone(),
+                previous_ordinal: previous.ordinal,
+            }),
+            _ => None,
+        };
+        if violation.is_none() || *self != OrdinalCheck::Strict {
+            *last = Some(LastWrite { block, key: key.to_string(), ordinal });
+        }
+
+        match self {
+            OrdinalCheck::Off => None,
+            _ => violation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{
+        delta::DeltaRecorder,
+        store::{MockStore, StoreConfig},
+    };
+
+    #[test]
+    #[should_panic(expected = "out of order write in block 1: key \"b\" at ordinal 1 comes after key \"a\" at ordinal 2")]
+    fn strict_panics_on_out_of_order_writes() {
+        let store = <MockStore as StoreNew>::new();
+        store.set_ordinal_check(OrdinalCheck::Strict);
+        store.begin_block(1);
+        store.set(2, "a", &1i64);
+        store.set(1, "b", &1i64);
+    }
+
+    #[test]
+    fn lenient_keeps_the_write_and_records_the_violation() {
+        let store = <MockStore as StoreNew>::new();
+        store.set_ordinal_check(OrdinalCheck::Lenient);
+        store.begin_block(1);
+        store.set(2, "a", &1i64);
+        store.set(1, "b", &1i64);
+
+        assert_eq!(store.store_deltas(1).len(), 2);
+        let violations = store.ordinal_violations();
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].key.as_str(), violations[0].previous_key.as_str()), ("b", "a"));
+    }
+
+    #[test]
+    fn stores_start_with_the_check_off() {
+        let store = <MockStore as StoreNew>::new();
+        store.set(2, "a", &1i64);
+        store.set(1, "b", &1i64);
+
+        assert_eq!(store.ordinal_check(), OrdinalCheck::Off);
+        assert!(store.ordinal_violations().is_empty());
+    }
+
+    #[test]
+    fn a_new_block_resets_the_check() {
+        let mut last = None;
+        let check = OrdinalCheck::Strict;
+        assert_eq!(check.record(&mut last, 1, "a", 5), None);
+        assert_eq!(check.record(&mut last, 2, "b", 1), None);
+        assert_eq!(
+            check.record(&mut last, 2, "c", 0),
+            Some(OrdinalViolation {
+                block: 2,
+                key: "c".to_string(),
+                ordinal: 0,
+                previous_key: "b".to_string(),
+                previous_ordinal: 1,
+            })
+        );
+        // a strict violation isn't recorded
+        assert_eq!(last.map(|last| last.key), Some("b".to_string()));
+        assert_eq!(OrdinalCheck::Off.record(&mut None, 1, "a", 0), None);
+    }
+}
@@ -7,8 +7,8 @@
 use prost::{DecodeError, EncodeError};
 
 /// Given an array of bytes, it will decode data in a Protobuf Message
-pub fn decode<T: Default + prost::Message>(buf: &Vec<u8>) -> Result<T, DecodeError> {
-    ::prost::Message::decode(&buf[..])
+pub fn decode<T: Default + prost::Message>(buf: &[u8]) -> Result<T, DecodeError> {
+    ::prost::Message::decode(buf)
 }
 
 /// Given a Protobuf message it will encode it and return the byte array.
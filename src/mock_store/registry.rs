@@ -0,0 +1,202 @@
+//! Wires store inputs by index, like the substreams runtime does.
+//!
+//! In a real module `StoreGet::new(idx)` returns a handle on the store that was
+//! declared at position `idx` in the module inputs. The mocks have no runtime to
+//! ask, so a `StoreRegistry` hands out indices for named stores and, once
+//! installed, `StoreGet::new(idx)` on any mock store returns a reader sharing
+//! the data of the store registered at that index.
+//!
+//! Installs only affect the current thread, so tests running in parallel can't
+//! see each other's stores. Threads a test spawns start with nothing installed,
+//! [`StoreRegistry::spawn`] installs a registry of `SyncMockStore`s on the new
+//! thread before running it.
+//!
+//! Indices are the order stores were registered in. The runtime numbers the
+//! `get` mode store inputs of each module, so give each handler a registry built
+//! for it, see `Manifest::registry_for`.
+//!
+//! ```no_run
+//! # use substreams::prelude::*;
+//! # use Stores_and_Deltas::mock_store::{registry::StoreRegistry, store::MockStore};
+//! let mut registry = StoreRegistry::new();
+//! let writer: MockStore = registry.create("store_totals");
+//! let idx = registry.index_of("store_totals").unwrap();
+//!
+//! let _installed = registry.install();
+//! let reader = <MockStore as StoreGet<BigInt>>::new(idx); // sees what writer wrote
+//! ```
+use std::{
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+    thread::{self, JoinHandle},
+};
+use crate::mock_store::{
+    handle::{Local, Shared, StoreHandle},
+    store::HasHandle,
+};
+
+// ids let a guard remove its own install, whatever order guards are dropped in
+static NEXT_INSTALL_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Default)]
+pub struct StoreRegistry<H: StoreHandle = Local> {
+    // the position in the vec is the store index
+    stores: Vec<(String, H)>,
+}
+
+impl<H: StoreHandle> StoreRegistry<H> {
+    pub fn new() -> Self {
+        Self { stores: Vec::new() }
+    }
+
+    /// Registers `store` under `name` and returns its index, registering the same
+    /// name twice replaces the store but keeps the index.
+    pub fn register<S: HasHandle<Handle = H>>(&mut self, name: &str, store: &S) -> u32 {
+        let handle = store.handle().clone();
+
+        match self.index_of(name) {
+            Some(idx) => {
+                self.stores[idx as usize].1 = handle;
+                idx
+            }
+            None => {
+                self.stores.push((name.to_string(), handle));
+                (self.stores.len() - 1) as u32
+            }
+        }
+    }
+
+    /// Creates an empty store, registers it under `name` and returns it.
+    pub fn create<S: HasHandle<Handle = H>>(&mut self, name: &str) -> S {
+        let store = S::from_handle(H::default());
+        self.register(name, &store);
+        store
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<u32> {
+        self.stores
+            .iter()
+            .position(|(registered, _)| registered == name)
+            .map(|idx| idx as u32)
+    }
+
+    pub fn name_of(&self, idx: u32) -> Option<&str> {
+        self.stores.get(idx as usize).map(|(name, _)| name.as_str())
+    }
+
+    /// Another handle on the store registered under `name`, as any store type.
+    pub fn get<S: HasHandle<Handle = H>>(&self, name: &str) -> Option<S> {
+        self.stores
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .map(|(_, handle)| S::from_handle(handle.clone()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.stores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stores.is_empty()
+    }
+
+    /// Makes `StoreGet::new(idx)` on the current thread resolve against this
+    /// registry until the returned guard is dropped. The last registry installed
+    /// and not yet dropped is the one used.
+    pub fn install(&self) -> InstalledRegistry<H> {
+        let id = NEXT_INSTALL_ID.fetch_add(1, Ordering::Relaxed);
+        let stores = self
+            .stores
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, handle))| (idx as u32, handle.clone()))
+            .collect();
+        H::with_installed(|installed| installed.push((id, stores)));
+
+        InstalledRegistry { id, not_send: PhantomData }
+    }
+}
+
+impl StoreRegistry<Shared> {
+    /// Spawns a thread running `f` with this registry installed.
+    pub fn spawn<T, F>(&self, f: F) -> JoinHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let registry = self.clone();
+        thread::spawn(move || {
+            let _installed = registry.install();
+            f()
+        })
+    }
+}
+
+/// Guard returned by [`StoreRegistry::install`].
+#[must_use = "the registry is uninstalled as soon as this guard is dropped"]
+pub struct InstalledRegistry<H: StoreHandle> {
+    id: u64,
+    // installs are per thread, so the guard has to be dropped on that thread
+    not_send: PhantomData<*const H>,
+}
+
+impl<H: StoreHandle> Drop for InstalledRegistry<H> {
+    fn drop(&mut self) {
+        H::with_installed(|installed| installed.retain(|(id, _)| *id != self.id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{handle::Shared, store::{MockStore, SyncMockStore}};
+
+    #[test]
+    fn new_resolves_installed_stores() {
+        let mut registry = StoreRegistry::new();
+        let writer: MockStore = registry.create("totals");
+        writer.set(1, "volume", &10i64);
+        let idx = registry.index_of("totals").unwrap();
+
+        let _installed = registry.install();
+        let reader = <MockStore as StoreGet<i64>>::new(idx);
+        assert_eq!(reader.get_last("volume"), Some(10));
+    }
+
+    #[test]
+    fn shared_stores_resolve_from_spawned_threads() {
+        let mut registry: StoreRegistry<Shared> = StoreRegistry::new();
+        let writer: SyncMockStore = registry.create("totals");
+        writer.set(1, "volume", &10i64);
+        let idx = registry.index_of("totals").unwrap();
+
+        let value = registry.spawn(move || <SyncMockStore as StoreGet<i64>>::new(idx).get_last("volume"));
+        assert_eq!(value.join().unwrap(), Some(10));
+
+        // nothing is installed on the other threads
+        let _installed = registry.install();
+        let value = std::thread::spawn(move || <SyncMockStore as StoreGet<i64>>::new(idx).get_last("volume"));
+        assert_eq!(value.join().unwrap(), None::<i64>);
+    }
+
+    #[test]
+    fn guards_only_uninstall_their_own_registry() {
+        let (mut first, mut second) = (StoreRegistry::new(), StoreRegistry::new());
+        let a: MockStore = first.create("a");
+        let b: MockStore = second.create("b");
+        a.set(1, "name", &"a".to_string());
+        b.set(1, "name", &"b".to_string());
+        let name = || <MockStore as StoreGet<String>>::new(0).get_last("name");
+
+        let first_installed = first.install();
+        let second_installed = second.install();
+        assert_eq!(name(), Some("b".to_string()));
+
+        // dropped out of order, the second registry stays installed
+        drop(first_installed);
+        assert_eq!(name(), Some("b".to_string()));
+        drop(second_installed);
+        assert_eq!(name(), None);
+    }
+}
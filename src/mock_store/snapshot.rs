@@ -0,0 +1,392 @@
+//! Store contents saved to disk.
+//!
+//! A snapshot is the full history of every key (all versions with their
+//! ordinals) plus the block the store was at and its value type, written as JSON
+//! so it can be checked in next to a test or looked at with the CLI. Values are
+//! `0x` hex since stores hold arbitrary bytes.
+//!
+//! ```json
+//! {
+//!   "value_type": "bigint",
+//!   "block": 13,
+//!   "entries": {
+//!     "volume": [{ "ordinal": 1, "value": "0x31323030" }]
+//!   }
+//! }
+//! ```
+//!
+//! Deltas are not part of a snapshot, a restored store starts without any.
+use std::{collections::BTreeMap, fmt, fs, io, path::Path, sync::Arc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use substreams::pb::substreams::{store_delta::Operation, StoreDelta};
+use crate::mock_store::{
+    handle::StoreHandle,
+    store::{HasHandle, StoreConfig},
+    value_type::ValueType,
+};
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// a delta doesn't fit the snapshot it is applied to
+    Conflict { key: String, reason: String },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "cannot read or write snapshot: {}", e),
+            SnapshotError::Json(e) => write!(f, "invalid snapshot: {}", e),
+            SnapshotError::Conflict { key, reason } => write!(f, "cannot apply delta to key {}: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotVersion {
+    pub ordinal: u64,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<ValueType>,
+    #[serde(default)]
+    pub block: u64,
+    /// key -> every version, oldest first, sorted by key so files diff nicely
+    #[serde(default)]
+    pub entries: BTreeMap<String, Vec<SnapshotVersion>>,
+    /// prefixes the store deleted, in call order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted_prefixes: Vec<String>,
+}
+
+impl Snapshot {
+    /// Copies the current content of `store`.
+    pub fn capture<S: HasHandle>(store: &S) -> Self {
+        let state = store.handle().read();
+        let entries = state
+            .kv
+            .iter()
+            .map(|(key, versions)| {
+                let versions = versions
+                    .iter()
+                    .map(|(ordinal, value)| SnapshotVersion { ordinal: *ordinal, value: value.clone() })
+                    .collect();
+                (key.clone(), versions)
+            })
+            .collect();
+
+        Snapshot {
+            value_type: state.value_type.clone(),
+            block: state.block,
+            entries,
+            deleted_prefixes: state.deleted_prefixes.clone(),
+        }
+    }
+
+    /// A new store holding the snapshot's content, no deltas are recorded for it.
+    pub fn restore<S: HasHandle>(&self) -> S {
+        let store = S::from_handle(Default::default());
+        {
+            let mut state = store.handle().write();
+            state.block = self.block;
+            state.deleted_prefixes = self.deleted_prefixes.clone();
+            state.kv = self
+                .entries
+                .iter()
+                .map(|(key, versions)| {
+                    let versions = versions.iter().map(|v| (v.ordinal, v.value.clone())).collect();
+                    (key.clone(), Arc::new(versions))
+                })
+                .collect();
+            state.recompute_total_size();
+        }
+        store.configure(None, self.value_type.clone());
+        store
+    }
+
+    /// Last value of `key`.
+    pub fn get_last(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).and_then(|versions| versions.last()).map(|v| v.value.as_slice())
+    }
+
+    /// The deltas turning the last values of `self` into those of `other`, sorted by key.
+    ///
+    /// Only last values are compared, a key rewritten with the same value isn't a change.
+    /// Ordinals are those of the versions the values come from.
+    pub fn diff(&self, other: &Snapshot) -> Vec<StoreDelta> {
+        let mut deltas = Vec::new();
+
+        for (key, versions) in &self.entries {
+            let Some(old) = versions.last() else { continue };
+            match other.entries.get(key).and_then(|v| v.last()) {
+                None => deltas.push(StoreDelta {
+                    operation: Operation::Delete as i32,
+                    ordinal: old.ordinal,
+                    key: key.clone(),
+                    old_value: old.value.clone(),
+                    new_value: Vec::new(),
+                }),
+                Some(new) if new.value != old.value => deltas.push(StoreDelta {
+                    operation: Operation::Update as i32,
+                    ordinal: new.ordinal,
+                    key: key.clone(),
+                    old_value: old.value.clone(),
+                    new_value: new.value.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        for (key, versions) in &other.entries {
+            if self.entries.get(key).is_some_and(|v| !v.is_empty()) {
+                continue;
+            }
+            if let Some(new) = versions.last() {
+                deltas.push(StoreDelta {
+                    operation: Operation::Create as i32,
+                    ordinal: new.ordinal,
+                    key: key.clone(),
+                    old_value: Vec::new(),
+                    new_value: new.value.clone(),
+                });
+            }
+        }
+
+        deltas.sort_by(|a, b| a.key.cmp(&b.key));
+        deltas
+    }
+
+    /// Replays the deltas of every block the way a sink rebuilds state: blocks in
+    /// ascending order, the deltas of a block in ordinal order (a stable sort, so
+    /// deltas sharing an ordinal keep their order). The snapshot ends up at the
+    /// highest block.
+    ///
+    /// The old value of every update and delete is checked against the last value
+    /// of its key, creates must target a missing key. Nothing is applied when a
+    /// delta doesn't fit.
+    pub fn apply(&mut self, blocks: &[(u64, Vec<StoreDelta>)]) -> Result<(), SnapshotError> {
+        // ordinals restart every block, so they only order deltas within one
+        let mut by_block: BTreeMap<u64, Vec<&StoreDelta>> = BTreeMap::new();
+        for (block, deltas) in blocks {
+            by_block.entry(*block).or_default().extend(deltas);
+        }
+        for deltas in by_block.values_mut() {
+            deltas.sort_by_key(|delta| delta.ordinal);
+        }
+        let deltas = by_block.values().flatten();
+
+        let mut entries = self.entries.clone();
+        for delta in deltas {
+            let conflict = |reason: String| SnapshotError::Conflict { key: delta.key.clone(), reason };
+            let last = entries.get(&delta.key).and_then(|versions| versions.last());
+
+            match Operation::try_from(delta.operation).unwrap_or(Operation::Unset) {
+                Operation::Create => {
+                    if last.is_some() {
+                        return Err(conflict("create of a key that already exists".to_string()));
+                    }
+                }
+                Operation::Update | Operation::Delete => match last {
+                    None => return Err(conflict("key doesn't exist".to_string())),
+                    Some(version) if version.value != delta.old_value => {
+                        return Err(conflict(format!(
+                            "old value 0x{} doesn't match the current 0x{}",
+                            hex::encode(&delta.old_value),
+                            hex::encode(&version.value)
+                        )));
+                    }
+                    Some(_) => {}
+                },
+                Operation::Unset => return Err(conflict("delta has no operation".to_string())),
+            }
+
+            if delta.operation == Operation::Delete as i32 {
+                entries.remove(&delta.key);
+            } else {
+                entries
+                    .entry(delta.key.clone())
+                    .or_default()
+                    .push(SnapshotVersion { ordinal: delta.ordinal, value: delta.new_value.clone() });
+            }
+        }
+
+        self.entries = entries;
+        if let Some(block) = by_block.keys().next_back() {
+            self.block = *block;
+        }
+        Ok(())
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, SnapshotError> {
+        Ok(serde_json::from_str(content)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots always serialize")
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+}
+
+fn to_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::{delta::DeltaRecorder, store::MockStore};
+
+    fn delta(operation: Operation, ordinal: u64, key: &str, old_value: &[u8], new_value: &[u8]) -> StoreDelta {
+        StoreDelta {
+            operation: operation as i32,
+            ordinal,
+            key: key.to_string(),
+            old_value: old_value.to_vec(),
+            new_value: new_value.to_vec(),
+        }
+    }
+
+    #[test]
+    fn snapshots_restore_what_was_captured() {
+        let store = <MockStore as StoreNew>::new();
+        store.configure(None, Some(ValueType::BigInt));
+        store.begin_block(13);
+        store.set(1, "volume", &BigInt::from(1200));
+        store.set(2, "volume", &BigInt::from(1300));
+        store.delete_prefix(3, &"pair:".to_string());
+
+        let snapshot = Snapshot::from_json(&Snapshot::capture(&store).to_json()).unwrap();
+        assert_eq!(snapshot.block, 13);
+        assert_eq!(snapshot.deleted_prefixes, vec!["pair:"]);
+
+        let restored: MockStore = snapshot.restore();
+        assert_eq!(<MockStore as StoreGet<BigInt>>::get_at(&restored, 1, "volume"), Some(BigInt::from(1200)));
+        assert_eq!(restored.value_type(), Some(ValueType::BigInt));
+        assert!(restored.recorded_deltas().is_empty());
+        assert_eq!(Snapshot::capture(&restored), snapshot);
+    }
+
+    fn snapshot(values: &[(&str, u64, &[u8])]) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        for (key, ordinal, value) in values {
+            snapshot
+                .entries
+                .entry(key.to_string())
+                .or_default()
+                .push(SnapshotVersion { ordinal: *ordinal, value: value.to_vec() });
+        }
+        snapshot
+    }
+
+    #[test]
+    fn diff_compares_last_values_sorted_by_key() {
+        let before = snapshot(&[("c", 1, b"1"), ("b", 1, b"1"), ("a", 1, b"1"), ("a", 2, b"2")]);
+        let after = snapshot(&[("a", 3, b"1"), ("a", 4, b"2"), ("b", 5, b"3"), ("d", 6, b"4")]);
+
+        assert_eq!(
+            before.diff(&after),
+            vec![
+                delta(Operation::Update, 5, "b", b"1", b"3"),
+                delta(Operation::Delete, 1, "c", b"1", b""),
+                delta(Operation::Create, 6, "d", b"", b"4"),
+            ]
+        );
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn applying_a_diff_gives_the_other_snapshot() {
+        let before = snapshot(&[("a", 1, b"1"), ("b", 1, b"1")]);
+        let after = snapshot(&[("a", 2, b"2"), ("c", 3, b"3")]);
+
+        let mut applied = before.clone();
+        applied.apply(&[(7, before.diff(&after))]).unwrap();
+        let last_values = |snapshot: &Snapshot| -> Vec<(String, Vec<u8>)> {
+            snapshot.entries.keys().map(|key| (key.clone(), snapshot.get_last(key).unwrap().to_vec())).collect()
+        };
+        assert_eq!(last_values(&applied), last_values(&after));
+    }
+
+    #[test]
+    fn apply_sorts_blocks_then_ordinals() {
+        let mut snapshot = Snapshot::default();
+        let blocks = vec![
+            (2, vec![delta(Operation::Update, 1, "volume", b"10", b"20")]),
+            // ordinals restart every block, block 1's higher one still comes first
+            (1, vec![delta(Operation::Create, 5, "volume", b"", b"10")]),
+        ];
+
+        snapshot.apply(&blocks).unwrap();
+        assert_eq!(snapshot.get_last("volume"), Some(&b"20"[..]));
+        assert_eq!(snapshot.entries["volume"].len(), 2);
+        assert_eq!(snapshot.block, 2);
+    }
+
+    #[test]
+    fn apply_sorts_the_deltas_of_a_block_by_ordinal() {
+        let mut snapshot = Snapshot::default();
+        let blocks = vec![(
+            1,
+            vec![
+                delta(Operation::Update, 3, "volume", b"10", b"20"),
+                delta(Operation::Delete, 4, "volume", b"20", b""),
+                delta(Operation::Create, 1, "volume", b"", b"10"),
+                delta(Operation::Create, 5, "volume", b"", b"30"),
+            ],
+        )];
+
+        snapshot.apply(&blocks).unwrap();
+        assert_eq!(snapshot.entries["volume"], vec![SnapshotVersion { ordinal: 5, value: b"30".to_vec() }]);
+    }
+
+    #[test]
+    fn apply_leaves_the_snapshot_alone_on_conflict() {
+        let mut snapshot = Snapshot::default();
+        snapshot.apply(&[(1, vec![delta(Operation::Create, 1, "volume", b"", b"10")])]).unwrap();
+
+        let blocks = vec![(
+            2,
+            vec![
+                delta(Operation::Create, 1, "count", b"", b"1"),
+                delta(Operation::Update, 2, "volume", b"99", b"20"),
+            ],
+        )];
+        let err = snapshot.apply(&blocks).unwrap_err();
+        assert!(matches!(err, SnapshotError::Conflict { ref key, .. } if key == "volume"));
+        assert_eq!(snapshot.get_last("count"), None);
+        assert_eq!(snapshot.block, 1);
+    }
+}
@@ -1,104 +1,195 @@
 //! Contains a mock store for internal testing.
 //!
 //! Might make this public alter to users can test their store handlers.
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
-use std::{ops::{Add, AddAssign}, cmp::PartialOrd};
+//!
+//! Every store is generic over its [`StoreHandle`], `MockStore`, `MockProtoStore` and
+//! `MockArrayStore` use the single threaded `Rc<RefCell<..>>` handle while the `Sync*`
+//! variants use `Arc<RwLock<..>>` so they can be shared across threads.
+use std::{ops::Add, cmp::PartialOrd};
 use substreams::{
-    prelude::{BigInt, StoreDelete, StoreGet, StoreSet, StoreNew, StoreAppend, StoreMax, StoreMin, Appender, StoreSetIfNotExists, BigDecimal},
-    store::{StoreAdd, StoreSetSum}
+    prelude::{StoreDelete, StoreGet, StoreSet, StoreNew, StoreMax, StoreMin, Appender, StoreSetIfNotExists},
+    store::StoreAdd
 };
 use std::marker::PhantomData;
-use crate::mock_store::traits::*;
+use substreams::pb::substreams::module::kind_store::UpdatePolicy;
+use crate::mock_store::{
+    handle::{Local, Shared, StoreHandle},
+    traits::*,
+    limits::StoreLimits,
+    ordinal::{OrdinalCheck, OrdinalViolation},
+    value_type::ValueType,
+};
+
+/// Gives access to the handle behind a mock store, so another store
+/// (a reader, a registry entry..) can be built on top of the same data.
+pub trait HasHandle {
+    type Handle: StoreHandle;
 
-type BytesMockStore = HashMap<String, Vec<(u64, Vec<u8>)>>; // wait why is it a Vec of a tuple and not just a tuple 
+    fn handle(&self) -> &Self::Handle;
 
-#[derive(Debug, Clone)]
-pub struct MockStore {
-    data: Rc<RefCell<BytesMockStore>>,
+    fn from_handle(handle: Self::Handle) -> Self;
 }
 
-impl StoreDelete for MockStore {
-    fn delete_prefix(&self, _ord: i64, prefix: &String) {
-        self.data
-            .borrow_mut()
-            .retain(|k, _| !k.starts_with(prefix));
+/// Prefix scans over the keys of a store.
+///
+/// The substreams stores can't list their keys, but tests constantly need to,
+/// keys always come back sorted.
+pub trait StoreScan {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String>;
+
+    fn keys(&self) -> Vec<String> {
+        self.keys_with_prefix("")
+    }
+}
+
+/// The `updatePolicy` and `valueType` a store was declared with, and the checks
+/// (limits, ordinal order) its writes go through.
+///
+/// Nothing is enforced from the policy and value type, they are there so tooling
+/// (loaders, dumps..) knows how the bytes of a store should be read.
+pub trait StoreConfig {
+    fn configure(&self, update_policy: Option<UpdatePolicy>, value_type: Option<ValueType>);
+
+    fn update_policy(&self) -> Option<UpdatePolicy>;
+
+    fn value_type(&self) -> Option<ValueType>;
+
+    /// Limits for the next writes, stores start with the runtime's.
+    fn set_limits(&self, limits: StoreLimits);
+
+    fn limits(&self) -> StoreLimits;
+
+    /// How out of order ordinals within a block are reported, off by default.
+    fn set_ordinal_check(&self, check: OrdinalCheck);
+
+    fn ordinal_check(&self) -> OrdinalCheck;
+
+    /// Out of order writes a lenient check let through, in write order.
+    fn ordinal_violations(&self) -> Vec<OrdinalViolation>;
+}
+
+impl<S: HasHandle> StoreConfig for S {
+    fn configure(&self, update_policy: Option<UpdatePolicy>, value_type: Option<ValueType>) {
+        let mut state = self.handle().write();
+        state.update_policy = update_policy;
+        state.value_type = value_type;
+    }
+
+    fn update_policy(&self) -> Option<UpdatePolicy> {
+        self.handle().read().update_policy
+    }
+
+    fn value_type(&self) -> Option<ValueType> {
+        self.handle().read().value_type.clone()
+    }
+
+    fn set_limits(&self, limits: StoreLimits) {
+        self.handle().write().limits = limits;
+    }
+
+    fn limits(&self) -> StoreLimits {
+        self.handle().read().limits
+    }
+
+    fn set_ordinal_check(&self, check: OrdinalCheck) {
+        self.handle().write().ordinal_check = check;
+    }
+
+    fn ordinal_check(&self) -> OrdinalCheck {
+        self.handle().read().ordinal_check
+    }
+
+    fn ordinal_violations(&self) -> Vec<OrdinalViolation> {
+        self.handle().read().ordinal_violations.clone()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BaseMockStore<H: StoreHandle> {
+    data: H,
+}
+
+pub type MockStore = BaseMockStore<Local>;
+
+/// `Send + Sync` version of [`MockStore`] for multi threaded test harnesses.
+pub type SyncMockStore = BaseMockStore<Shared>;
+
+impl<H: StoreHandle> HasHandle for BaseMockStore<H> {
+    type Handle = H;
+
+    fn handle(&self) -> &H {
+        &self.data
+    }
+
+    fn from_handle(handle: H) -> Self {
+        Self { data: handle }
     }
 }
 
-impl StoreNew for MockStore {
+impl<H: StoreHandle> StoreScan for BaseMockStore<H> {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.data.keys_with_prefix(prefix)
+    }
+}
+
+impl<H: StoreHandle> StoreDelete for BaseMockStore<H> {
+    fn delete_prefix(&self, ord: i64, prefix: &String) {
+        self.data.delete_prefix(ord as u64, prefix);
+    }
+}
+
+impl<H: StoreHandle> StoreNew for BaseMockStore<H> {
     fn new() -> Self {
-        Self { data: Rc::new(RefCell::new(HashMap::new())) }
+        Self { data: H::default() }
     }
 }
 
 
-impl <T: FromBytes> StoreGet<T> for MockStore {
-    fn new(_idx: u32) -> Self {
-        Self { data: Rc::new(RefCell::new(HashMap::new())) }
+impl <H: StoreHandle, T: FromBytes> StoreGet<T> for BaseMockStore<H> {
+    /// Reads the store installed at `idx` by a `StoreRegistry`, or an empty one if there is none.
+    fn new(idx: u32) -> Self {
+        Self { data: H::resolve(idx) }
     }
 
     fn get_at<K: AsRef<str>>(&self, ord: u64, key: K) -> Option<T> {
         self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| {
-                entries
-                    .iter()
-                    .find(|(current_ord, _)| *current_ord == ord)
-                    .map(|(_, bytes)| get_value_from_bytes::<T>(bytes)) 
-            })
+            .get_bytes_at(ord, key.as_ref())
+            .map(|bytes| get_value_from_bytes::<T>(&bytes))
     }
 
     fn get_last<K: AsRef<str>>(&self, key: K) -> Option<T> {
         self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| { // vec
-                entries.last().map(|(_,bytes)| { // gets the last element of the slice (that is of &Vec<(u64,Vec<u8>)>), ignores the ord, thats the u64
-                    get_value_from_bytes::<T>(bytes)
-                })
-            })
+            .get_bytes_last(key.as_ref()) // ignores the ord, thats the u64
+            .map(|bytes| get_value_from_bytes::<T>(&bytes))
     }
 
     fn get_first<K: AsRef<str>>(&self, key: K) -> Option<T> {
-            self.data
-                .borrow()
-                .get(&key.as_ref().to_string())
-                .and_then(|entries| {
-                    entries.first().map(|(_,bytes)| {
-                        get_value_from_bytes::<T>(bytes)
-                    })
-                })
+        self.data
+            .get_bytes_first(key.as_ref())
+            .map(|bytes| get_value_from_bytes::<T>(&bytes))
     }
 
     fn has_at<K: AsRef<str>>(&self, ord: u64, key: K) -> bool {
-        self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .map(|v| v.iter().any(|(v, _)| *v == ord))
-            .unwrap_or(false)
-    } 
+        self.data.has_bytes_at(ord, key.as_ref())
+    }
 
     fn has_last<K: AsRef<str>>(&self, key: K) -> bool {
-        <MockStore as StoreGet<T>>::get_last::<K>(&self, key).is_some() // we specify the type explicitly because there is more than one trait bound
+        <BaseMockStore<H> as StoreGet<T>>::get_last::<K>(self, key).is_some() // we specify the type explicitly because there is more than one trait bound
     }
 
     fn has_first<K: AsRef<str>>(&self, key: K) -> bool {
-        <MockStore as StoreGet<T>>::get_first::<K>(&self, key).is_some()
+        <BaseMockStore<H> as StoreGet<T>>::get_first::<K>(self, key).is_some()
     }
 }
 
 
 
-impl <T: ToString + ToBytes> StoreSet<T> for MockStore {  
+impl <H: StoreHandle, T: ToString + ToBytes> StoreSet<T> for BaseMockStore<H> {
     /// Set a given key to a given value, if the key existed before, it will be replaced.
     fn set<K: AsRef<str>>(&self, ord: u64, key: K, value: &T) {
-        let mut guard = self.data.borrow_mut();
-        guard
-            .entry(key.as_ref().to_string())
-            .or_insert(vec![(ord, convert_value_to_bytes(value))]); //how does it work without the generic type with starfish loll
+        self.data.push_bytes(ord, key.as_ref(), convert_value_to_bytes(value));
     }
-    
+
     /// Set many keys to a given value, if the key existed before, it will be replaced.
     fn set_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: &T) {
         keys.iter().for_each(|key| self.set(ord, key, value));
@@ -106,53 +197,42 @@ impl <T: ToString + ToBytes> StoreSet<T> for MockStore {
 }
 
 
-impl <T: ToString + ToBytes> StoreSetIfNotExists<T> for MockStore {
+impl <H: StoreHandle, T: ToString + ToBytes> StoreSetIfNotExists<T> for BaseMockStore<H> {
     fn set_if_not_exists<K: AsRef<str>>(&self, ord: u64, key: K, value: &T) {
-        let mut guard = self.data.borrow_mut();
-
-        if !guard.contains_key(key.as_ref()) {             
-            guard
-                .entry(key.as_ref().to_string())
-                .or_insert(vec![(ord, convert_value_to_bytes(value))]); //how does it work without the generic type with starfish loll
-        }
+        self.data
+            .update_bytes(ord, key.as_ref(), |current| current.is_none().then(|| convert_value_to_bytes(value)));
     }
 
     fn set_if_not_exists_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: &T) {
         keys
             .iter()
-            .for_each(|key| self.set_if_not_exists(ord, key, value)); // 
+            .for_each(|key| self.set_if_not_exists(ord, key, value)); //
     }
 }
 
 //bigInt
-//i64 
+//i64
 //f64
 //BigDecimal for add min and max
 
 //convert bytes to value
-//add 
-//convert back to bytes 
-//store 
-impl<T> StoreAdd<T> for MockStore 
-where 
-    T : FromBytes + ToBytes + Add + AddAssign + ToString + Clone { //try to reduce trait bounds here 
+//add
+//convert back to bytes
+//store
+impl<H, T> StoreAdd<T> for BaseMockStore<H>
+where
+    H: StoreHandle,
+    T : FromBytes + ToBytes + Add<Output = T> + ToString + Clone { // Add and not AddAssign, BigInt only has the former
     // add a check for non negative values ?
-    fn add<K: AsRef<str>>(&self, ord: u64, key: K, value: T) { // this 
-        let mut guard = self.data.borrow_mut();
-        guard
-            .entry(key.as_ref().to_string())
-            .and_modify(|v| {
-                // first decode the value, add, then encode to bytes back
-                let prev_value = v.last().unwrap().1.clone();
-                let mut decoded_val = get_value_from_bytes::<T>(&prev_value);
-
-                let val = format!("{:?}", decoded_val += value.clone()); // this might be wrong?
-
-                let bytes_val = val.as_bytes(); 
-
-                v.push((ord, bytes_val.to_vec())); 
-            })
-            .or_insert(vec![(ord, convert_value_to_bytes(&value))]);  //convert the T to a Vec<u8> 
+    fn add<K: AsRef<str>>(&self, ord: u64, key: K, value: T) {
+        // first decode the value, add, then encode to bytes back
+        self.data.update_bytes(ord, key.as_ref(), |prev_value| {
+            let sum = match prev_value {
+                Some(prev_value) => get_value_from_bytes::<T>(prev_value) + value,
+                None => value,
+            };
+            Some(convert_value_to_bytes(&sum)) //convert the T to a Vec<u8>
+        });
     }
 
     fn add_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: T) {
@@ -161,135 +241,136 @@ where
 }
 
 
-//means set_if_value is larger else don't 
-  
+//means set_if_value is larger else don't
+
 /// max will set the provided key in the store only if the value received in
-/// parameter is bigger than the one already present in the store, with
-/// a default of the zero value when the key is absent.
-impl <T> StoreMax<T> for MockStore
-where 
+/// parameter is bigger than the one already present in the store, an absent
+/// key is set to the value as is.
+impl <H, T> StoreMax<T> for BaseMockStore<H>
+where
+    H: StoreHandle,
     T : FromBytes + ToBytes + PartialOrd + ToString {
     fn max<K: AsRef<str>>(&self, ord: u64, key: K, value: T) {
-        let mut guard = self.data.borrow_mut();
-        let key_val = self.data // to abstract this in a macro or helper fn?
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| { // vec
-                entries.last().map(|(_,bytes)| { // gets the last element of the slice (that is of &Vec<(u64,Vec<u8>)>), ignores the ord, thats the u64
-                    get_value_from_bytes::<T>(bytes)
-                })
-            })
-            .unwrap_or_else(|| panic!("cannot get_last value: key {} not found", key.as_ref()));
-        //if hash_map does not contain the key, then insert 0 in its place
-        if !guard.contains_key(key.as_ref()) {
-             guard
-                .entry(key.as_ref().to_string())
-                .or_insert(vec![(ord, [0u8].to_vec())]); // is this correct
-        }
-
-        //if hash mpa contains the key and the new_value we want to insert is > 
-        // our current value for that key then replace it with the new_value
-        if guard.contains_key(key.as_ref()) && value > key_val {             
-            guard
-                .entry(key.as_ref().to_string())
-                .or_insert(vec![(ord, convert_value_to_bytes(&value))]);
-        } 
+        self.data.update_bytes(ord, key.as_ref(), |current| {
+            //if hash map does not contain the key, or the new_value we want to insert is >
+            // our current value for that key then replace it with the new_value
+            let current = current.map(get_value_from_bytes::<T>);
+            current.is_none_or(|key_val| value > key_val).then(|| convert_value_to_bytes(&value))
+        });
     }
 }
 
 /// Will set the provided key in the store only if the value received in
-/// parameter is smaller than the one already present in the store, with
-/// a default of the zero value when the key is absent.
-impl <T> StoreMin<T> for MockStore
-where 
+/// parameter is smaller than the one already present in the store, an absent
+/// key is set to the value as is.
+impl <H, T> StoreMin<T> for BaseMockStore<H>
+where
+    H: StoreHandle,
     T : FromBytes + ToBytes + PartialOrd + ToString {
     fn min<K: AsRef<str>>(&self, ord: u64, key: K, value: T) {
-        let mut guard = self.data.borrow_mut();
-        let key_val = self.data 
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| { // vec
-                entries.last().map(|(_,bytes)| { // gets the last element of the slice (that is of &Vec<(u64,Vec<u8>)>), ignores the ord, thats the u64
-                    get_value_from_bytes::<T>(bytes)
-                })
-            })
-            .unwrap_or_else(|| panic!("cannot get_last value: key {} not found", key.as_ref()));
-            // .expect(&format!("failed to get last value for key {:?}", key)); /
-        //if hash_map does not contain the key, then insert 0 in its place
-        if !guard.contains_key(key.as_ref()) {
-             guard
-                .entry(key.as_ref().to_string())
-                .or_insert(vec![(ord, [0u8].to_vec())]);
-        }
-
-        //if hash map contains the key and the new_value we want to insert is <
-        // our current value for that key then replace it with the new_value
-        if guard.contains_key(key.as_ref()) && value < key_val {             
-            guard
-                .entry(key.as_ref().to_string())
-                .or_insert(vec![(ord, convert_value_to_bytes(&value))]);
-        } 
+        self.data.update_bytes(ord, key.as_ref(), |current| {
+            //if hash map does not contain the key, or the new_value we want to insert is <
+            // our current value for that key then replace it with the new_value
+            let current = current.map(get_value_from_bytes::<T>);
+            current.is_none_or(|key_val| value < key_val).then(|| convert_value_to_bytes(&value))
+        });
     }
 }
 
 
-impl<T> Appender<T> for MockStore
-where 
+impl<H, T> Appender<T> for BaseMockStore<H>
+where
+    H: StoreHandle,
     T: Into<String>,
-{  
+{
     fn new() -> Self {
-        MockStore {
-            data: Rc::new(RefCell::new(HashMap::new()))
-        }
+        Self { data: H::default() }
     }
 
     fn append<K: AsRef<str>>(&self, ord: u64, key: K, item: T) {
         let item_str: String = item.into();
-        let mut formatted = format!("{};", &item_str).as_bytes().to_vec();
-        let mut guard = self.data.borrow_mut();
-        let entry = guard 
-                     .entry(key.as_ref().to_string())
-                     .and_modify(|existing| {
-                        existing[0].0 = ord;
-                        existing[0].1.append(&mut formatted)
-                    })
-                     .or_insert(vec![(ord, (formatted))]);
+        self.data.update_bytes(ord, key.as_ref(), |current| {
+            let mut appended = current.unwrap_or_default().to_vec();
+            appended.extend_from_slice(format!("{};", &item_str).as_bytes());
+            Some(appended)
+        });
     }
 
     fn append_all<K: AsRef<str>>(&self, ord: u64, key: K, items: Vec<T>) {
          let key_str = key.as_ref().to_string();
-        items.into_iter().for_each(|item| self.append(ord, &key_str, item)) // move to take ownership of item so we dont have a "From<&T> not implemented for String" trait bound error 
+        items.into_iter().for_each(|item| self.append(ord, &key_str, item)) // move to take ownership of item so we dont have a "From<&T> not implemented for String" trait bound error
     }
 }
 
 
-//Proto trait impls 
+//Proto trait impls
 //StoreSetProto
 //StoreSetIfNotExistsProto
 
-//we are using different Stores -> that is MockProtoStore instread of the MockStore so we don't have 
-//conflicting trait implementations -> this should be obvious lol 
+//we are using different Stores -> that is MockProtoStore instread of the MockStore so we don't have
+//conflicting trait implementations -> this should be obvious lol
 
-pub struct MockProtoStore<T> { 
-    data: Rc<RefCell<BytesMockStore>>, 
-    phantom: PhantomData<T> // do we need the <T> lol add the trait bound to MockProtoStore 
+pub struct BaseMockProtoStore<T, H: StoreHandle> {
+    data: H,
+    phantom: PhantomData<T> // do we need the <T> lol add the trait bound to MockProtoStore
     //the extra trait bounds are not necessary for StoreNew and StoreDelete
-    //okay i had to add it to impl this must_get_last method 
+    //okay i had to add it to impl this must_get_last method
+}
+
+pub type MockProtoStore<T> = BaseMockProtoStore<T, Local>;
+
+/// `Send + Sync` version of [`MockProtoStore`].
+pub type SyncMockProtoStore<T> = BaseMockProtoStore<T, Shared>;
+
+impl<T, H: StoreHandle> HasHandle for BaseMockProtoStore<T, H> {
+    type Handle = H;
+
+    fn handle(&self) -> &H {
+        &self.data
+    }
+
+    fn from_handle(handle: H) -> Self {
+        Self {
+            data: handle,
+            phantom: PhantomData
+        }
+    }
+}
+
+// derive would want T: Clone, but we only clone the handle
+impl<T, H: StoreHandle> Clone for BaseMockProtoStore<T, H> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+            phantom: PhantomData
+        }
+    }
 }
-impl<T> StoreDelete for MockProtoStore<T> { } // no delete method for proto, but was impl so the trait bounds will be satisfied and stfu
 
-impl<T> StoreNew for MockProtoStore<T> {
+impl<T, H: StoreHandle> StoreScan for BaseMockProtoStore<T, H> {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.data.keys_with_prefix(prefix)
+    }
+}
+
+impl<T, H: StoreHandle> StoreDelete for BaseMockProtoStore<T, H> {
+    fn delete_prefix(&self, ord: i64, prefix: &String) {
+        self.data.delete_prefix(ord as u64, prefix);
+    }
+}
+
+impl<T, H: StoreHandle> StoreNew for BaseMockProtoStore<T, H> {
     fn new() -> Self {
-        Self { 
-            data: Rc::new(RefCell::new(HashMap::new())),
+        Self {
+            data: H::default(),
             phantom: PhantomData
         }
     }
 }
 
-impl <T: FromBytesProto> MockProtoStore<T> { 
+impl <T: FromBytesProto, H: StoreHandle> BaseMockProtoStore<T, H> {
     //honestly the only reason for the mockstore generic is because of this method loll
-    //its also in the substreams code but we could have done without it 
+    //its also in the substreams code but we could have done without it
      pub fn must_get_last<K: AsRef<str>>(&self, key: K) -> T {
         self.get_last(&key)
             .unwrap_or_else(|| panic!("cannot get_last value: key {} not found", key.as_ref()))
@@ -297,76 +378,54 @@ impl <T: FromBytesProto> MockProtoStore<T> {
 }
 
 
- impl <T: FromBytesProto> StoreGet<T> for MockProtoStore<T> {
-    fn new(_idx: u32) -> Self {
-        Self { 
-            data: Rc::new(RefCell::new(HashMap::new())),
+ impl <T: FromBytesProto, H: StoreHandle> StoreGet<T> for BaseMockProtoStore<T, H> {
+    /// Reads the store installed at `idx` by a `StoreRegistry`, or an empty one if there is none.
+    fn new(idx: u32) -> Self {
+        Self {
+            data: H::resolve(idx),
             phantom: PhantomData
         }
     }
 
     fn get_at<K: AsRef<str>>(&self, ord: u64, key: K) -> Option<T> {
         self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| {
-                entries
-                    .iter()
-                    .find(|(current_ord, _)| *current_ord == ord)
-                    .map(|(_, bytes)| get_value_from_bytes_proto::<T>(bytes)) 
-            })
+            .get_bytes_at(ord, key.as_ref())
+            .map(|bytes| get_value_from_bytes_proto::<T>(&bytes))
     }
 
     fn get_last<K: AsRef<str>>(&self, key: K) -> Option<T> {
         self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| { // vec
-                entries.last().map(|(_,bytes)| { // gets the last element of the slice (that is of &Vec<(u64,Vec<u8>)>), ignores the ord, thats the u64
-                    get_value_from_bytes_proto::<T>(bytes)
-                })
-            })
+            .get_bytes_last(key.as_ref()) // ignores the ord, thats the u64
+            .map(|bytes| get_value_from_bytes_proto::<T>(&bytes))
     }
 
     fn get_first<K: AsRef<str>>(&self, key: K) -> Option<T> {
-            self.data
-                .borrow()
-                .get(&key.as_ref().to_string())
-                .and_then(|entries| {
-                    entries.first().map(|(_,bytes)| {
-                        get_value_from_bytes_proto::<T>(bytes)
-                    })
-                })
+        self.data
+            .get_bytes_first(key.as_ref())
+            .map(|bytes| get_value_from_bytes_proto::<T>(&bytes))
     }
 
     fn has_at<K: AsRef<str>>(&self, ord: u64, key: K) -> bool {
-        self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .map(|v| v.iter().any(|(v, _)| *v == ord))
-            .unwrap_or(false)
-    } 
+        self.data.has_bytes_at(ord, key.as_ref())
+    }
 
     fn has_last<K: AsRef<str>>(&self, key: K) -> bool {
-        <MockProtoStore<T> as StoreGet<T>>::get_last::<K>(&self, key).is_some() // we specify the type explicitly because there is more than one trait bound
+        <BaseMockProtoStore<T, H> as StoreGet<T>>::get_last::<K>(self, key).is_some() // we specify the type explicitly because there is more than one trait bound
     }
 
     fn has_first<K: AsRef<str>>(&self, key: K) -> bool {
-        <MockProtoStore<T> as StoreGet<T>>::get_first::<K>(&self, key).is_some()
+        <BaseMockProtoStore<T, H> as StoreGet<T>>::get_first::<K>(self, key).is_some()
     }
 }
 
 
 
-impl <T: ToBytesProto> StoreSet<T> for MockProtoStore<T>  {  
+impl <T: ToBytesProto, H: StoreHandle> StoreSet<T> for BaseMockProtoStore<T, H>  {
     /// Set a given key to a given value, if the key existed before, it will be replaced.
     fn set<K: AsRef<str>>(&self, ord: u64, key: K, value: &T) {
-        let mut guard = self.data.borrow_mut();
-        guard
-            .entry(key.as_ref().to_string())
-            .or_insert(vec![(ord, convert_value_to_bytes_proto(value))]); //::<T> ??
+        self.data.push_bytes(ord, key.as_ref(), convert_value_to_bytes_proto(value)); //::<T> ??
     }
-    
+
     /// Set many keys to a given value, if the key existed before, it will be replaced.
     fn set_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: &T) {
         keys.iter().for_each(|key| self.set(ord, key, value));
@@ -374,93 +433,99 @@ impl <T: ToBytesProto> StoreSet<T> for MockProtoStore<T>  {
 }
 
 
-impl <T: ToBytesProto> StoreSetIfNotExists<T> for MockProtoStore<T> {
+impl <T: ToBytesProto, H: StoreHandle> StoreSetIfNotExists<T> for BaseMockProtoStore<T, H> {
     fn set_if_not_exists<K: AsRef<str>>(&self, ord: u64, key: K, value: &T) {
-        let mut guard = self.data.borrow_mut();
-
-        if !guard.contains_key(key.as_ref()) {             
-            guard
-                .entry(key.as_ref().to_string())
-                .or_insert(vec![(ord, convert_value_to_bytes_proto(value))]);  //::<T> ??
-        }
+        self.data
+            .update_bytes(ord, key.as_ref(), |current| current.is_none().then(|| convert_value_to_bytes_proto(value)));
     }
 
     fn set_if_not_exists_many<K: AsRef<str>>(&self, ord: u64, keys: &Vec<K>, value: &T) {
         keys
             .iter()
-            .for_each(|key| self.set_if_not_exists(ord, key, value)); // 
+            .for_each(|key| self.set_if_not_exists(ord, key, value)); //
     }
 }
 
 
 //StoreGetArray
 
-pub struct MockArrayStore {
-    data: Rc<RefCell<BytesMockStore>>, 
+#[derive(Debug, Clone, Default)]
+pub struct BaseMockArrayStore<H: StoreHandle> {
+    data: H,
+}
+
+pub type MockArrayStore = BaseMockArrayStore<Local>;
+
+/// `Send + Sync` version of [`MockArrayStore`].
+pub type SyncMockArrayStore = BaseMockArrayStore<Shared>;
+
+impl<H: StoreHandle> HasHandle for BaseMockArrayStore<H> {
+    type Handle = H;
+
+    fn handle(&self) -> &H {
+        &self.data
+    }
+
+    fn from_handle(handle: H) -> Self {
+        Self { data: handle }
+    }
+}
+
+impl<H: StoreHandle> StoreScan for BaseMockArrayStore<H> {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.data.keys_with_prefix(prefix)
+    }
+}
+
+impl<H: StoreHandle> StoreDelete for BaseMockArrayStore<H> {
+    fn delete_prefix(&self, ord: i64, prefix: &String) {
+        self.data.delete_prefix(ord as u64, prefix);
+    }
 }
 
-impl<T: Into<String> + From<String>> StoreGet<Vec<T>> for MockArrayStore {
+impl<H: StoreHandle, T: Into<String> + From<String>> StoreGet<Vec<T>> for BaseMockArrayStore<H> {
+    /// Reads the store installed at `idx` by a `StoreRegistry`, or an empty one if there is none.
     fn new(idx: u32) -> Self {
         Self {
-            data: Rc::new(RefCell::new(HashMap::new()))
+            data: H::resolve(idx)
         }
     }
 
     fn get_at<K: AsRef<str>>(&self, ord: u64, key: K) -> Option<Vec<T>> {
          self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| {
-                entries
-                    .iter()
-                    .find(|(current_ord, _)| *current_ord == ord)
-                    .map(|(_, bytes)| split_array(bytes.clone()).unwrap_or_else(|| panic!("failed to split array")))
-            })
+            .get_bytes_at(ord, key.as_ref())
+            .map(|bytes| split_array(bytes).unwrap_or_else(|| panic!("failed to split array")))
     }
 
     fn get_last<K: AsRef<str>>(&self, key: K) -> Option<Vec<T>> {
          self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .and_then(|entries| { // vec
-                entries.last().map(|(_,bytes)| { // gets the last element of the slice (that is of &Vec<(u64,Vec<u8>)>), ignores the ord, thats the u64
-                    split_array(bytes.clone()).unwrap_or_else(|| panic!("failed to split array"))
-                })
-            })
+            .get_bytes_last(key.as_ref()) // ignores the ord, thats the u64
+            .map(|bytes| split_array(bytes).unwrap_or_else(|| panic!("failed to split array")))
     }
 
     fn get_first<K: AsRef<str>>(&self, key: K) -> Option<Vec<T>> {
          self.data
-                .borrow()
-                .get(&key.as_ref().to_string())
-                .and_then(|entries| {
-                    entries.first().map(|(_,bytes)| {
-                        split_array(bytes.clone()).unwrap_or_else(|| panic!("failed to split array"))
-                    })
-                })
+            .get_bytes_first(key.as_ref())
+            .map(|bytes| split_array(bytes).unwrap_or_else(|| panic!("failed to split array")))
     }
 
     fn has_at<K: AsRef<str>>(&self, ord: u64, key: K) -> bool {
-          self.data
-            .borrow()
-            .get(&key.as_ref().to_string())
-            .map(|v| v.iter().any(|(v, _)| *v == ord))
-            .unwrap_or(false)
+        self.data.has_bytes_at(ord, key.as_ref())
     }
 
     fn has_last<K: AsRef<str>>(&self, key: K) -> bool {
-        <MockArrayStore as StoreGet<Vec<T>>>::get_last::<K>(&self, key).is_some() 
+        <BaseMockArrayStore<H> as StoreGet<Vec<T>>>::get_last::<K>(self, key).is_some()
     }
 
     fn has_first<K: AsRef<str>>(&self, key: K) -> bool {
-        <MockArrayStore as StoreGet<Vec<T>>>::get_last::<K>(&self, key).is_some() 
+        <BaseMockArrayStore<H> as StoreGet<Vec<T>>>::get_last::<K>(self, key).is_some()
     }
 }
 
-fn split_array<T: Into<String> + From<String>>(bytes: Vec<u8>) -> Option<Vec<T>> {
+pub(crate) fn split_array<T: Into<String> + From<String>>(bytes: Vec<u8>) -> Option<Vec<T>> {
     let chunks: Vec<_> = bytes
         .split(|b|*b == b';') // split slice by semicolon
-        .filter(|x| x.len() > 0)
+        .filter(|x| !x.is_empty())
         .map(|part| {
             String::from_utf8(part.into())
                 .unwrap_or_else(|_| panic!("Invalid UTF-8 sequence in store value"))
@@ -488,3 +553,82 @@ fn split_array<T: Into<String> + From<String>>(bytes: Vec<u8>) -> Option<Vec<T>>
 
 // https://github.com/streamingfast/substreams-rs/blob/995a9bfcc15ebd59df63bdb2ce1b5d095d189d06/substreams-macro/src/handler.rs
 
+#[cfg(test)]
+mod tests {
+    use substreams::scalar::BigInt;
+    use super::*;
+
+    #[test]
+    fn set_replaces_and_keeps_history() {
+        let store = <MockStore as StoreNew>::new();
+        store.set(1, "volume", &BigInt::from(10));
+        store.set(2, "volume", &BigInt::from(20));
+
+        assert_eq!(<MockStore as StoreGet<BigInt>>::get_last(&store, "volume"), Some(BigInt::from(20)));
+        assert_eq!(<MockStore as StoreGet<BigInt>>::get_first(&store, "volume"), Some(BigInt::from(10)));
+        assert_eq!(<MockStore as StoreGet<BigInt>>::get_at(&store, 1, "volume"), Some(BigInt::from(10)));
+    }
+
+    #[test]
+    fn add_sums_onto_the_last_value() {
+        let store = <MockStore as StoreNew>::new();
+        store.add(1, "volume", BigInt::from(10));
+        store.add(2, "volume", BigInt::from(5));
+        assert_eq!(<MockStore as StoreGet<BigInt>>::get_last(&store, "volume"), Some(BigInt::from(15)));
+    }
+
+    #[test]
+    fn max_and_min_write_absent_keys_and_keep_the_extreme() {
+        let store = <MockStore as StoreNew>::new();
+        store.max(1, "high", 5i64);
+        store.max(2, "high", 3i64);
+        store.max(3, "high", 8i64);
+        store.min(1, "low", 5i64);
+        store.min(2, "low", 8i64);
+        store.min(3, "low", -1i64);
+
+        assert_eq!(<MockStore as StoreGet<i64>>::get_last(&store, "high"), Some(8));
+        assert_eq!(<MockStore as StoreGet<i64>>::get_last(&store, "low"), Some(-1));
+    }
+
+    #[test]
+    fn append_records_a_version_per_item() {
+        let store = <MockStore as StoreNew>::new();
+        store.append(1, "pools", "0xab".to_string());
+        store.append(2, "pools", "0xcd".to_string());
+
+        assert_eq!(store.handle().get_bytes_first("pools"), Some(b"0xab;".to_vec()));
+        assert_eq!(store.handle().get_bytes_last("pools"), Some(b"0xab;0xcd;".to_vec()));
+    }
+
+    #[test]
+    fn sync_stores_are_written_from_other_threads() {
+        let store = <SyncMockStore as StoreNew>::new();
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..500 {
+                        store.add(0, "count", 1i64);
+                        store.append(0, "items", "x");
+                    }
+                })
+            })
+            .collect();
+        writers.into_iter().for_each(|writer| writer.join().unwrap());
+
+        // every read-modify-write went through, none was lost to another thread
+        assert_eq!(<SyncMockStore as StoreGet<i64>>::get_last(&store, "count"), Some(4000));
+        assert_eq!(store.handle().get_bytes_last("items").map(|items| items.len()), Some(8000));
+    }
+
+    #[test]
+    fn set_if_not_exists_keeps_the_first_value() {
+        let store = <MockStore as StoreNew>::new();
+        store.set_if_not_exists(1, "owner", &"0xab".to_string());
+        store.set_if_not_exists(2, "owner", &"0xcd".to_string());
+
+        assert_eq!(<MockStore as StoreGet<String>>::get_last(&store, "owner"), Some("0xab".to_string()));
+        assert_eq!(store.handle().deltas_at(0).len(), 1);
+    }
+}
@@ -0,0 +1,443 @@
+//! Protobuf text format rendering of proto values.
+//!
+//! prost messages carry no descriptor, so their field names and types come from a
+//! [`DescriptorPool`] built from the package's file descriptors: a `.spkg` or a
+//! `protoc --descriptor_set_out` file, both read as a `FileDescriptorSet`. Values
+//! are decoded straight off the wire with it, on one line:
+//!
+//! ```text
+//! address: "0xab" fee: 3 token0 { symbol: "WETH" }
+//! ```
+//!
+//! ```no_run
+//! # use Stores_and_Deltas::mock_store::{store::MockStore, text_format::DescriptorPool};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let store = MockStore::default();
+//! let pool = DescriptorPool::from_file("uniswap-v3.spkg")?;
+//! // stores declared with a proto:pools.v1.Pool value type
+//! println!("{}", store.dump().descriptors(&pool));
+//! # Ok(())
+//! # }
+//! ```
+use std::{collections::HashMap, fmt, fs, io, path::Path};
+use prost_types::{
+    field_descriptor_proto::Type, DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorProto,
+    FileDescriptorSet,
+};
+use crate::mock_store::{format::FormatValue, store::StoreConfig, value_type::ValueType};
+
+#[derive(Debug)]
+pub enum TextFormatError {
+    Io(io::Error),
+    /// the descriptors themselves don't decode
+    Descriptors(String),
+    UnknownMessage(String),
+    /// the value doesn't decode as the message
+    Malformed { message: String, reason: String },
+}
+
+impl fmt::Display for TextFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextFormatError::Io(e) => write!(f, "cannot read descriptors: {}", e),
+            TextFormatError::Descriptors(e) => write!(f, "invalid descriptors: {}", e),
+            TextFormatError::UnknownMessage(message) => write!(f, "no descriptor for message {}", message),
+            TextFormatError::Malformed { message, reason } => write!(f, "invalid {} value: {}", message, reason),
+        }
+    }
+}
+
+impl std::error::Error for TextFormatError {}
+
+impl From<io::Error> for TextFormatError {
+    fn from(e: io::Error) -> Self {
+        TextFormatError::Io(e)
+    }
+}
+
+/// Message and enum descriptors by full name, without the leading dot.
+#[derive(Debug, Clone, Default)]
+pub struct DescriptorPool {
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, EnumDescriptorProto>,
+}
+
+impl DescriptorPool {
+    pub fn new(files: &[FileDescriptorProto]) -> Self {
+        let mut pool = Self::default();
+        for file in files {
+            let scope = file.package();
+            for message in &file.message_type {
+                pool.add_message(scope, message);
+            }
+            for enum_type in &file.enum_type {
+                pool.enums.insert(full_name(scope, enum_type.name()), enum_type.clone());
+            }
+        }
+        pool
+    }
+
+    /// Reads an encoded `FileDescriptorSet`, a `Package` reads as one since its
+    /// proto files are the same field.
+    pub fn decode(bytes: &[u8]) -> Result<Self, TextFormatError> {
+        let set: FileDescriptorSet =
+            substreams::proto::decode(&bytes.to_vec()).map_err(|e| TextFormatError::Descriptors(e.to_string()))?;
+        Ok(Self::new(&set.file))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, TextFormatError> {
+        Self::decode(&fs::read(path)?)
+    }
+
+    fn add_message(&mut self, scope: &str, message: &DescriptorProto) {
+        let name = full_name(scope, message.name());
+        for nested in &message.nested_type {
+            self.add_message(&name, nested);
+        }
+        for enum_type in &message.enum_type {
+            self.enums.insert(full_name(&name, enum_type.name()), enum_type.clone());
+        }
+        self.messages.insert(name, message.clone());
+    }
+
+    /// `bytes` decoded as `message` and rendered in text format, see the module docs.
+    pub fn format(&self, message: &str, bytes: &[u8]) -> Result<String, TextFormatError> {
+        let message = message.trim_start_matches('.');
+        let descriptor = self
+            .messages
+            .get(message)
+            .ok_or_else(|| TextFormatError::UnknownMessage(message.to_string()))?;
+
+        let mut parts = Vec::new();
+        self.write_message(descriptor, bytes, &mut parts).map_err(|reason| TextFormatError::Malformed {
+            message: message.to_string(),
+            reason,
+        })?;
+        Ok(parts.join(" "))
+    }
+
+    // one part per field, or per element of a packed field
+    fn write_message(&self, descriptor: &DescriptorProto, bytes: &[u8], parts: &mut Vec<String>) -> Result<(), String> {
+        let mut reader = WireReader { bytes, pos: 0 };
+        while !reader.done() {
+            let tag = reader.varint()?;
+            let (number, wire_type) = ((tag >> 3) as i32, (tag & 7) as u8);
+            let value = reader.value(wire_type)?;
+
+            let Some(field) = descriptor.field.iter().find(|field| field.number() == number) else {
+                parts.push(format!("{}: {}", number, unknown_value(&value)));
+                continue;
+            };
+
+            match (field.r#type(), value) {
+                (Type::Message, WireValue::Bytes(bytes)) => {
+                    let nested = self
+                        .messages
+                        .get(field.type_name().trim_start_matches('.'))
+                        .ok_or_else(|| format!("no descriptor for message {}", field.type_name()))?;
+                    let mut inner = Vec::new();
+                    self.write_message(nested, bytes, &mut inner)?;
+                    match inner.is_empty() {
+                        true => parts.push(format!("{} {{}}", field.name())),
+                        false => parts.push(format!("{} {{ {} }}", field.name(), inner.join(" "))),
+                    }
+                }
+                (Type::String, WireValue::Bytes(bytes)) => {
+                    let s = std::str::from_utf8(bytes).map_err(|_| format!("field {} is not valid UTF-8", field.name()))?;
+                    parts.push(format!("{}: \"{}\"", field.name(), escape_str(s)));
+                }
+                (Type::Bytes, WireValue::Bytes(bytes)) => {
+                    parts.push(format!("{}: \"{}\"", field.name(), escape(bytes)));
+                }
+                // packed repeated scalars
+                (_, WireValue::Bytes(bytes)) => {
+                    let wire_type = scalar_wire_type(field.r#type())
+                        .ok_or_else(|| format!("field {} has an unsupported type", field.name()))?;
+                    let mut packed = WireReader { bytes, pos: 0 };
+                    while !packed.done() {
+                        let element = packed.value(wire_type)?;
+                        parts.push(format!("{}: {}", field.name(), self.scalar(field, element)?));
+                    }
+                }
+                (_, value) => parts.push(format!("{}: {}", field.name(), self.scalar(field, value)?)),
+            }
+        }
+        Ok(())
+    }
+
+    fn scalar(&self, field: &FieldDescriptorProto, value: WireValue) -> Result<String, String> {
+        let text = match (field.r#type(), value) {
+            (Type::Int64, WireValue::Varint(v)) => (v as i64).to_string(),
+            (Type::Uint64, WireValue::Varint(v)) => v.to_string(),
+            (Type::Int32, WireValue::Varint(v)) => (v as i32).to_string(),
+            (Type::Uint32, WireValue::Varint(v)) => (v as u32).to_string(),
+            (Type::Sint32, WireValue::Varint(v)) => (zigzag(v) as i32).to_string(),
+            (Type::Sint64, WireValue::Varint(v)) => zigzag(v).to_string(),
+            (Type::Bool, WireValue::Varint(v)) => (v != 0).to_string(),
+            (Type::Enum, WireValue::Varint(v)) => {
+                let number = v as i32;
+                self.enums
+                    .get(field.type_name().trim_start_matches('.'))
+                    .and_then(|enum_type| enum_type.value.iter().find(|value| value.number() == number))
+                    .map(|value| value.name().to_string())
+                    // values the descriptor doesn't know are kept as numbers, like protoc does
+                    .unwrap_or_else(|| number.to_string())
+            }
+            (Type::Fixed32, WireValue::Fixed32(v)) => v.to_string(),
+            (Type::Sfixed32, WireValue::Fixed32(v)) => (v as i32).to_string(),
+            (Type::Float, WireValue::Fixed32(v)) => f32::from_bits(v).to_string(),
+            (Type::Fixed64, WireValue::Fixed64(v)) => v.to_string(),
+            (Type::Sfixed64, WireValue::Fixed64(v)) => (v as i64).to_string(),
+            (Type::Double, WireValue::Fixed64(v)) => f64::from_bits(v).to_string(),
+            (field_type, _) => {
+                return Err(format!("field {} has the wrong wire type for {}", field.name(), field_type.as_str_name()))
+            }
+        };
+        Ok(text)
+    }
+}
+
+/// `store.format_value(bytes)`, or text format when `pool` is given and knows
+/// the store's declared `proto:` value type.
+pub fn format_value<S: FormatValue + StoreConfig>(store: &S, pool: Option<&DescriptorPool>, bytes: &[u8]) -> String {
+    let text = match (pool, store.value_type()) {
+        (Some(pool), Some(ValueType::Proto(message))) => pool.format(&message, bytes).ok(),
+        _ => None,
+    };
+    text.unwrap_or_else(|| store.format_value(bytes))
+}
+
+fn full_name(scope: &str, name: &str) -> String {
+    match scope.is_empty() {
+        true => name.to_string(),
+        false => format!("{}.{}", scope, name),
+    }
+}
+
+fn zigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+fn scalar_wire_type(field_type: Type) -> Option<u8> {
+    match field_type {
+        Type::Int64 | Type::Uint64 | Type::Int32 | Type::Uint32 | Type::Sint32 | Type::Sint64 | Type::Bool | Type::Enum => {
+            Some(0)
+        }
+        Type::Fixed64 | Type::Sfixed64 | Type::Double => Some(1),
+        Type::Fixed32 | Type::Sfixed32 | Type::Float => Some(5),
+        _ => None,
+    }
+}
+
+// what protoc --decode_raw shows for fields the descriptor doesn't have
+fn unknown_value(value: &WireValue) -> String {
+    match value {
+        WireValue::Varint(v) => v.to_string(),
+        WireValue::Fixed32(v) => format!("0x{:08x}", v),
+        WireValue::Fixed64(v) => format!("0x{:016x}", v),
+        WireValue::Bytes(bytes) => format!("\"{}\"", escape(bytes)),
+    }
+}
+
+// text format string escapes, non printable bytes as octal
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            b'"' => "\\\"".to_string(),
+            b'\\' => "\\\\".to_string(),
+            b'\n' => "\\n".to_string(),
+            b'\r' => "\\r".to_string(),
+            b'\t' => "\\t".to_string(),
+            0x20..0x7f => (b as char).to_string(),
+            _ => format!("\\{:03o}", b),
+        })
+        .collect()
+}
+
+// strings keep their non ASCII characters as is
+fn escape_str(s: &str) -> String {
+    s.chars()
+        .map(|c| match c.is_ascii() {
+            true => escape(&[c as u8]),
+            false => c.to_string(),
+        })
+        .collect()
+}
+
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+struct WireReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    fn done(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|end| *end <= self.bytes.len()).ok_or("truncated value")?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint longer than 10 bytes".to_string())
+    }
+
+    fn value(&mut self, wire_type: u8) -> Result<WireValue<'a>, String> {
+        match wire_type {
+            0 => Ok(WireValue::Varint(self.varint()?)),
+            1 => Ok(WireValue::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().expect("took 8 bytes")))),
+            2 => {
+                let len = self.varint()? as usize;
+                Ok(WireValue::Bytes(self.take(len)?))
+            }
+            5 => Ok(WireValue::Fixed32(u32::from_le_bytes(self.take(4)?.try_into().expect("took 4 bytes")))),
+            // groups are proto2 only and long deprecated
+            other => Err(format!("unsupported wire type {}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prost_types::EnumValueDescriptorProto;
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::store::MockProtoStore;
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    struct Pool {
+        #[prost(string, tag = "1")]
+        address: String,
+        #[prost(uint64, tag = "2")]
+        fee: u64,
+        #[prost(message, optional, tag = "3")]
+        token0: Option<Token>,
+        #[prost(enumeration = "Kind", tag = "4")]
+        kind: i32,
+        #[prost(sint64, repeated, tag = "5")]
+        ticks: Vec<i64>,
+        #[prost(bytes = "vec", tag = "6")]
+        raw: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    struct Token {
+        #[prost(string, tag = "1")]
+        symbol: String,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, ::prost::Enumeration)]
+    enum Kind {
+        Unknown = 0,
+        Stable = 1,
+    }
+
+    fn field(name: &str, number: i32, field_type: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            r#type: Some(field_type as i32),
+            type_name: type_name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn pool() -> DescriptorPool {
+        let pool = DescriptorProto {
+            name: Some("Pool".to_string()),
+            field: vec![
+                field("address", 1, Type::String, None),
+                field("fee", 2, Type::Uint64, None),
+                field("token0", 3, Type::Message, Some(".pools.v1.Pool.Token")),
+                field("kind", 4, Type::Enum, Some(".pools.v1.Kind")),
+                field("ticks", 5, Type::Sint64, None),
+                field("raw", 6, Type::Bytes, None),
+            ],
+            nested_type: vec![DescriptorProto {
+                name: Some("Token".to_string()),
+                field: vec![field("symbol", 1, Type::String, None)],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let kind = EnumDescriptorProto {
+            name: Some("Kind".to_string()),
+            value: ["UNKNOWN", "STABLE"]
+                .iter()
+                .zip(0..)
+                .map(|(name, number)| EnumValueDescriptorProto { name: Some(name.to_string()), number: Some(number), options: None })
+                .collect(),
+            ..Default::default()
+        };
+
+        DescriptorPool::new(&[FileDescriptorProto {
+            package: Some("pools.v1".to_string()),
+            message_type: vec![pool],
+            enum_type: vec![kind],
+            ..Default::default()
+        }])
+    }
+
+    fn encoded(pool: &Pool) -> Vec<u8> {
+        crate::mock_store::proto::encode(pool).unwrap()
+    }
+
+    #[test]
+    fn messages_render_in_text_format() {
+        let value = Pool {
+            address: "0x\"ab\"".to_string(),
+            fee: 3,
+            token0: Some(Token { symbol: "WETH".to_string() }),
+            kind: Kind::Stable as i32,
+            ticks: vec![-2, 5],
+            raw: vec![0x01, b'a'],
+        };
+
+        assert_eq!(
+            pool().format(".pools.v1.Pool", &encoded(&value)).unwrap(),
+            r#"address: "0x\"ab\"" fee: 3 token0 { symbol: "WETH" } kind: STABLE ticks: -2 ticks: 5 raw: "\001a""#
+        );
+        assert_eq!(pool().format("pools.v1.Pool", &[]).unwrap(), "");
+    }
+
+    #[test]
+    fn unknown_fields_and_messages() {
+        // field 9 as a varint, which the descriptor doesn't have
+        assert_eq!(pool().format("pools.v1.Pool", &[0x48, 0x07]).unwrap(), "9: 7");
+        assert!(matches!(pool().format("pools.v1.Swap", &[]), Err(TextFormatError::UnknownMessage(_))));
+        assert!(matches!(pool().format("pools.v1.Pool", &[0x0a, 0x05]), Err(TextFormatError::Malformed { .. })));
+    }
+
+    #[test]
+    fn dumps_use_the_declared_message() {
+        let store = <MockProtoStore<Pool> as StoreNew>::new();
+        store.configure(None, Some(ValueType::Proto("pools.v1.Pool".to_string())));
+        store.set(1, "pool:0xab", &Pool { address: "0xab".to_string(), fee: 3, ..Default::default() });
+
+        let descriptors = pool();
+        assert_eq!(
+            store.dump().descriptors(&descriptors).to_string(),
+            "MockProtoStore<Pool>, 1 key\n\"pool:0xab\"\n  #1 address: \"0xab\" fee: 3"
+        );
+        assert!(store.to_string().ends_with("#1 Pool { address: \"0xab\", fee: 3, token0: None, kind: Unknown, ticks: [], raw: [] }"));
+    }
+}
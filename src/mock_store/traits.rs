@@ -2,7 +2,7 @@ use std::str::FromStr;
 use substreams::{
     prelude::{BigInt,BigDecimal}
 };
-use prost::{Message, Name};
+use prost::Message;
 use crate::mock_store::proto;
 
 /// Converts &[u8] into the expected value type
@@ -54,9 +54,10 @@ impl FromBytes for String {
     }
 }
 
+// stored as the decimal string, same as ToBytes below and the substreams runtime
 impl FromBytes for BigInt {
     fn from_bytes(bytes: &[u8]) -> Self {
-        BigInt::from_unsigned_bytes_be(bytes)
+        BigInt::from_store_bytes(bytes)
     }
 }
 
@@ -86,8 +87,7 @@ where
     T: Message + Default,
 {
     fn from_bytes(bytes: &[u8]) -> Self {
-        let s = proto::decode(&bytes.to_vec()).expect("error when decoding");
-        s
+        proto::decode(bytes).expect("error when decoding")
     }   
 }
 
@@ -116,9 +116,20 @@ where
     T: Message + Default
 {
     fn to_bytes(&self) -> Vec<u8> {
-        let s = proto::encode(self).expect("error when encoding proto");
-        s
+        proto::encode(self).expect("error when encoding proto")
     }   
 }
 
-  
\ No newline at end of file
+  
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_numbers_round_trip_through_their_decimal_string() {
+        let big = BigInt::from(u64::MAX) * BigInt::from(u64::MAX);
+        assert_eq!(convert_value_to_bytes(&big), big.to_string().into_bytes());
+        assert_eq!(get_value_from_bytes::<BigInt>(&convert_value_to_bytes(&big)), big);
+        assert_eq!(get_value_from_bytes::<i64>(b"-3"), -3);
+    }
+}
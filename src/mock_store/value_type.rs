@@ -0,0 +1,173 @@
+//! Value types a store can be declared with in a substreams manifest.
+//!
+//! The mocks only ever hold bytes, the value type says how to read them back,
+//! same names as the `valueType` field of a store module.
+use std::{fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use substreams::prelude::{BigDecimal, BigInt};
+use crate::mock_store::traits::convert_value_to_bytes;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ValueType {
+    BigInt,
+    /// `bigdecimal`, `bigfloat` is accepted as an alias like the runtime does
+    BigDecimal,
+    Int64,
+    Float64,
+    String,
+    Bytes,
+    /// `proto:<fully qualified message name>`
+    Proto(String),
+}
+
+impl ValueType {
+    /// Encodes a JSON value the way a live write of this value type would, through `ToBytes`.
+    ///
+    /// Numbers may be given as JSON numbers or strings, big ones have to be strings,
+    /// bytes are hex strings. Protos have no generic encoding, they go through serde
+    /// with their concrete type instead, see `fixture::Fixture::seed_proto`.
+    pub fn encode_json(&self, value: &Value) -> Result<Vec<u8>, String> {
+        // numbers as text, so "1200" and 1200 are both fine
+        let as_text = || match value {
+            Value::String(s) => Ok(s.clone()),
+            Value::Number(n) => Ok(n.to_string()),
+            other => Err(format!("expected a number or a string for {}, got {}", self, other)),
+        };
+
+        match self {
+            ValueType::BigInt => {
+                let v = BigInt::from_str(&as_text()?).map_err(|e| format!("invalid bigint: {}", e))?;
+                Ok(convert_value_to_bytes(&v))
+            }
+            ValueType::BigDecimal => {
+                let v = BigDecimal::from_str(&as_text()?).map_err(|e| format!("invalid bigdecimal: {}", e))?;
+                Ok(convert_value_to_bytes(&v))
+            }
+            ValueType::Int64 => {
+                let v = as_text()?.parse::<i64>().map_err(|e| format!("invalid int64: {}", e))?;
+                Ok(convert_value_to_bytes(&v))
+            }
+            ValueType::Float64 => {
+                let v = as_text()?.parse::<f64>().map_err(|e| format!("invalid float64: {}", e))?;
+                Ok(convert_value_to_bytes(&v))
+            }
+            ValueType::String => match value {
+                Value::String(s) => Ok(convert_value_to_bytes(s)),
+                other => Err(format!("expected a string, got {}", other)),
+            },
+            ValueType::Bytes => match value {
+                Value::String(s) => {
+                    hex::decode(s.trim_start_matches("0x")).map_err(|e| format!("invalid hex bytes: {}", e))
+                }
+                other => Err(format!("expected a hex string, got {}", other)),
+            },
+            ValueType::Proto(message) => Err(format!("{} values need their concrete proto type to be encoded", message)),
+        }
+    }
+
+    /// The other way around from [`ValueType::encode_json`], for exports.
+    ///
+    /// Big numbers stay strings so nothing loses precision, bytes, protos and
+    /// anything that doesn't decode come out as `0x` hex strings.
+    pub fn decode_json(&self, bytes: &[u8]) -> Value {
+        self.try_decode_json(bytes)
+            .unwrap_or_else(|| Value::String(format!("0x{}", hex::encode(bytes))))
+    }
+
+    /// [`ValueType::decode_json`] without the hex fallback, `None` for bytes, protos
+    /// and values that don't decode.
+    pub(crate) fn try_decode_json(&self, bytes: &[u8]) -> Option<Value> {
+        let text = std::str::from_utf8(bytes).ok();
+
+        let decoded = match self {
+            ValueType::BigInt => text.and_then(|s| BigInt::from_str(s).ok()).map(|v| Value::String(v.to_string())),
+            ValueType::BigDecimal => text.and_then(|s| BigDecimal::from_str(s).ok()).map(|v| Value::String(v.to_string())),
+            ValueType::Int64 => text.and_then(|s| s.parse::<i64>().ok()).map(Value::from),
+            ValueType::Float64 => text.and_then(|s| s.parse::<f64>().ok()).map(Value::from),
+            ValueType::String => text.map(Value::from),
+            ValueType::Bytes | ValueType::Proto(_) => None,
+        };
+
+        // NaN and infinities have no JSON form and come out null
+        decoded.filter(|value| !value.is_null())
+    }
+}
+
+impl FromStr for ValueType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(message) = s.strip_prefix("proto:") {
+            if message.is_empty() {
+                return Err("proto value type is missing its message name".to_string());
+            }
+            return Ok(ValueType::Proto(message.to_string()));
+        }
+
+        match s {
+            "bigint" => Ok(ValueType::BigInt),
+            "bigdecimal" | "bigfloat" => Ok(ValueType::BigDecimal),
+            "int64" => Ok(ValueType::Int64),
+            "float64" => Ok(ValueType::Float64),
+            "string" => Ok(ValueType::String),
+            "bytes" => Ok(ValueType::Bytes),
+            _ => Err(format!("unknown value type {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::BigInt => write!(f, "bigint"),
+            ValueType::BigDecimal => write!(f, "bigdecimal"),
+            ValueType::Int64 => write!(f, "int64"),
+            ValueType::Float64 => write!(f, "float64"),
+            ValueType::String => write!(f, "string"),
+            ValueType::Bytes => write!(f, "bytes"),
+            ValueType::Proto(message) => write!(f, "proto:{}", message),
+        }
+    }
+}
+
+impl From<ValueType> for String {
+    fn from(value_type: ValueType) -> Self {
+        value_type.to_string()
+    }
+}
+
+impl TryFrom<String> for ValueType {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn value_types_parse_like_the_manifest_names_them() {
+        assert_eq!("bigfloat".parse::<ValueType>(), Ok(ValueType::BigDecimal));
+        assert_eq!("proto:pools.v1.Pool".parse::<ValueType>(), Ok(ValueType::Proto("pools.v1.Pool".to_string())));
+        assert!("proto:".parse::<ValueType>().is_err());
+        assert!("uint64".parse::<ValueType>().is_err());
+        assert_eq!(ValueType::BigDecimal.to_string(), "bigdecimal");
+    }
+
+    #[test]
+    fn json_values_round_trip() {
+        let big = json!("340282366920938463463374607431768211455");
+        let bytes = ValueType::BigInt.encode_json(&big).unwrap();
+        assert_eq!(ValueType::BigInt.decode_json(&bytes), big);
+
+        assert_eq!(ValueType::Int64.encode_json(&json!(12)), ValueType::Int64.encode_json(&json!("12")));
+        assert_eq!(ValueType::Bytes.decode_json(&ValueType::Bytes.encode_json(&json!("0xab01")).unwrap()), json!("0xab01"));
+        assert_eq!(ValueType::Int64.decode_json(b"abc"), json!("0x616263"));
+    }
+}
@@ -0,0 +1,111 @@
+//! Read-only views over the mock stores.
+//!
+//! Downstream modules get their store inputs in `get` mode, they can read but
+//! never write. Handing them a `ReadOnlyStore` instead of the store itself makes
+//! the compiler enforce that, a view only implements `StoreGet` and `StoreScan`
+//! while still sharing the data of the store it was made from.
+use substreams::prelude::StoreGet;
+use crate::mock_store::store::StoreScan;
+
+#[derive(Debug, Clone)]
+pub struct ReadOnlyStore<S> {
+    store: S,
+}
+
+impl<S: Clone> ReadOnlyStore<S> {
+    /// A view sharing the data of `store`, writes to `store` show up in the view.
+    pub fn of(store: &S) -> Self {
+        Self { store: store.clone() }
+    }
+}
+
+impl<S: Clone> From<&S> for ReadOnlyStore<S> {
+    fn from(store: &S) -> Self {
+        Self::of(store)
+    }
+}
+
+impl<S: StoreScan> ReadOnlyStore<S> {
+    /// Last value of every key starting with `prefix`, sorted by key.
+    pub fn scan_prefix<T>(&self, prefix: &str) -> Vec<(String, T)>
+    where
+        S: StoreGet<T>,
+    {
+        self.store
+            .keys_with_prefix(prefix)
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.store.get_last(&key)?;
+                Some((key, value))
+            })
+            .collect()
+    }
+}
+
+impl<S: StoreScan> StoreScan for ReadOnlyStore<S> {
+    fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.store.keys_with_prefix(prefix)
+    }
+}
+
+// everything is forwarded to the wrapped store, only the writing traits are left out
+impl<T, S: StoreGet<T>> StoreGet<T> for ReadOnlyStore<S> {
+    fn new(idx: u32) -> Self {
+        Self { store: S::new(idx) }
+    }
+
+    fn get_at<K: AsRef<str>>(&self, ord: u64, key: K) -> Option<T> {
+        self.store.get_at(ord, key)
+    }
+
+    fn get_last<K: AsRef<str>>(&self, key: K) -> Option<T> {
+        self.store.get_last(key)
+    }
+
+    fn get_first<K: AsRef<str>>(&self, key: K) -> Option<T> {
+        self.store.get_first(key)
+    }
+
+    fn has_at<K: AsRef<str>>(&self, ord: u64, key: K) -> bool {
+        self.store.has_at(ord, key)
+    }
+
+    fn has_last<K: AsRef<str>>(&self, key: K) -> bool {
+        self.store.has_last(key)
+    }
+
+    fn has_first<K: AsRef<str>>(&self, key: K) -> bool {
+        self.store.has_first(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use substreams::prelude::*;
+    use super::*;
+    use crate::mock_store::store::MockStore;
+
+    #[test]
+    fn views_see_later_writes() {
+        let store = <MockStore as StoreNew>::new();
+        let view = ReadOnlyStore::of(&store);
+        store.set(1, "volume", &10i64);
+        assert_eq!(<ReadOnlyStore<MockStore> as StoreGet<i64>>::get_last(&view, "volume"), Some(10));
+    }
+
+    #[test]
+    fn scan_prefix_returns_sorted_last_values() {
+        let store = <MockStore as StoreNew>::new();
+        store.set(1, "pool:0xcd", &2i64);
+        store.set(2, "pool:0xab", &1i64);
+        store.set(3, "pool:0xab", &3i64);
+        store.set(4, "token:0xef", &4i64);
+
+        let view = ReadOnlyStore::of(&store);
+        assert_eq!(
+            view.scan_prefix::<i64>("pool:"),
+            vec![("pool:0xab".to_string(), 3), ("pool:0xcd".to_string(), 2)]
+        );
+        assert_eq!(view.keys(), vec!["pool:0xab", "pool:0xcd", "token:0xef"]);
+    }
+}